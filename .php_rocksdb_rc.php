@@ -5,45 +5,114 @@
  */
 class RocksDB {
     /**
-     * Creates a new RocksDB instance with the specified path and TTL.
+     * Creates a new RocksDB instance with the specified path, TTL and options.
+     * With 'persistent' => true the handle is kept open and reused for the lifetime of the worker process.
      * @param string $path
      * @param int|null $ttl_secs
+     * @param array|null $options
+     * @throws \RocksDB\Exception\LockTimeoutException if lock_timeout_ms passes before the lock file is free
      */
-    public function __construct(string $path, ?int $ttl_secs = null) {}
+    public function __construct(string $path, ?int $ttl_secs = null, ?array $options = null) {}
+
+    /**
+     * Opens an existing database for reading only, without taking the lock file.
+     * Accepts the constructor options except transactional and persistent, plus error_if_wal_file_exists.
+     * @param string $path
+     * @param array|null $options
+     * @return RocksDB
+     */
+    public static function openReadOnly(string $path, ?array $options = null): RocksDB {}
+
+    /**
+     * Opens a secondary instance of the database at the primary path, without taking the lock file.
+     * @param string $primary_path
+     * @param string $secondary_path Directory for the secondary's own logs
+     * @param array|null $options
+     * @return RocksDB
+     */
+    public static function openAsSecondary(string $primary_path, string $secondary_path, ?array $options = null): RocksDB {}
+
+    /**
+     * Catches a secondary instance up with what the primary has written.
+     * @return void
+     */
+    public function tryCatchUpWithPrimary() {}
 
     /**
      * Inserts a key-value pair into the database.
      * @param string $key
      * @param string $value
      * @param string|null $cf_name
+     * @param array|null $write_options sync, disable_wal, no_slowdown, low_pri
      * @return void
      */
-    public function put(string $key, string $value, ?string $cf_name = null) {}
+    public function put(string $key, string $value, ?string $cf_name = null, ?array $write_options = null) {}
 
     /**
      * Retrieves the value associated with the given key.
      * @param string $key
      * @param string|null $cf_name
+     * @param array|null $read_options fill_cache, verify_checksums, readahead_size, tailing, prefix_same_as_start, total_order_seek
      * @return string|null
      */
-    public function get(string $key, ?string $cf_name = null): ?string {}
+    public function get(string $key, ?string $cf_name = null, ?array $read_options = null): ?string {}
+
+    /**
+     * Retrieves the values of several keys in one call, keyed by the requested key.
+     * Missing keys map to null.
+     * @param string[] $keys
+     * @param string|null $cf_name
+     * @param array|null $read_options
+     * @return array
+     */
+    public function multiGet(array $keys, ?string $cf_name = null, ?array $read_options = null): array {}
+
+    /**
+     * Retrieves keys from several column families in one call.
+     * @param array $keys_by_cf Lists of keys keyed by column family name
+     * @param array|null $read_options
+     * @return array Values keyed by column family name, then by key
+     */
+    public function multiGetCf(array $keys_by_cf, ?array $read_options = null): array {}
 
     /**
      * Merges a value into the database.
      * @param string $key
      * @param string $value
      * @param string|null $cf_name
+     * @param array|null $write_options sync, disable_wal, no_slowdown, low_pri
      * @return void
      */
-    public function merge(string $key, string $value, ?string $cf_name = null) {}
+    public function merge(string $key, string $value, ?string $cf_name = null, ?array $write_options = null) {}
 
     /**
      * Deletes the key-value pair associated with the given key.
      * @param string $key
      * @param string|null $cf_name
+     * @param array|null $write_options sync, disable_wal, no_slowdown, low_pri
      * @return void
      */
-    public function delete(string $key, ?string $cf_name = null) {}
+    public function delete(string $key, ?string $cf_name = null, ?array $write_options = null) {}
+
+    /**
+     * Deletes all keys from $from (inclusive) to $to (exclusive).
+     * @param string $from
+     * @param string $to
+     * @param string|null $cf_name
+     * @param array|null $write_options sync, disable_wal, no_slowdown, low_pri
+     * @return void
+     */
+    public function deleteRange(string $from, string $to, ?string $cf_name = null, ?array $write_options = null) {}
+
+    /**
+     * Deletes all keys starting with the prefix, optionally compacting the range afterwards.
+     * @param string $prefix
+     * @param string|null $cf_name
+     * @param bool|null $compact
+     * @param array|null $write_options sync, disable_wal, no_slowdown, low_pri
+     * @return void
+     */
+    public function deletePrefix(string $prefix, ?string $cf_name = null, ?bool $compact = null, ?array $write_options = null) {}
 
     /**
      * Lists all column families in the database.
@@ -53,11 +122,12 @@ class RocksDB {
     public static function listColumnFamilies(string $path): array {}
 
     /**
-     * Creates a new column family with the specified name.
+     * Creates a new column family with the specified name and options.
      * @param string $cf_name
+     * @param array|null $options per column family options, see the README; unset ones are inherited
      * @return void
      */
-    public function createColumnFamily(string $cf_name) {}
+    public function createColumnFamily(string $cf_name, ?array $options = null) {}
 
     /**
      * Drops the column family with the specified name.
@@ -89,11 +159,34 @@ class RocksDB {
     public static function repair(string $path) {}
 
     /**
-     * Closes the RocksDB instance.
+     * Creates a copy of the open database in a new directory, hard-linking SST files where possible.
+     * @param string $dir
      * @return void
      */
+    public function createCheckpoint(string $dir) {}
+
+    /**
+     * Writes the contents of a column family to export.sst in a new directory.
+     * @param string $cf_name
+     * @param string $dir
+     * @return string|null path of the file, null if the column family is empty
+     */
+    public function exportColumnFamily(string $cf_name, string $dir): ?string {}
+
+    /**
+     * Closes the RocksDB instance and releases its lock file.
+     * Later calls on the instance throw \RocksDB\Exception\ClosedException.
+     * @return void
+     * @throws \RocksDB\Exception\RocksDBException if iterators, snapshots, batches or transactions still use it
+     */
     public function close() {}
 
+    /**
+     * Checks whether the instance is still open.
+     * @return bool
+     */
+    public function isOpen(): bool {}
+
     /**
      * Returns all key-value pairs in the database or column family.
      * @param string|null $cf_name
@@ -108,6 +201,67 @@ class RocksDB {
      */
     public function keys(?string $cf_name = null): array {}
 
+    /**
+     * Returns key-value pairs between the bounds in key order, one page at a time.
+     * The result is ["entries" => [...], "next" => ?string]; pass "next" as $token to continue.
+     * @param string|null $from Inclusive lower bound
+     * @param string|null $to Exclusive upper bound
+     * @param int|null $limit Maximum number of entries, 0 or null for no limit
+     * @param bool|null $reverse
+     * @param string|null $cf_name
+     * @param string|null $token
+     * @param array|null $read_options
+     * @return array
+     */
+    public function scan(?string $from = null, ?string $to = null, ?int $limit = null, ?bool $reverse = null, ?string $cf_name = null, ?string $token = null, ?array $read_options = null): array {}
+
+    /**
+     * Returns key-value pairs starting with the prefix in key order, one page at a time.
+     * @param string $prefix
+     * @param int|null $limit Maximum number of entries, 0 or null for no limit
+     * @param bool|null $reverse
+     * @param string|null $cf_name
+     * @param string|null $token
+     * @param array|null $read_options
+     * @return array
+     */
+    public function scanPrefix(string $prefix, ?int $limit = null, ?bool $reverse = null, ?string $cf_name = null, ?string $token = null, ?array $read_options = null): array {}
+
+    /**
+     * Creates a snapshot of the current state of the database.
+     * @return RocksDBSnapshot
+     */
+    public function snapshot(): RocksDBSnapshot {}
+
+    /**
+     * Creates a write batch that writes to this database. The batch is already started.
+     * @return RocksDBWriteBatch
+     */
+    public function createWriteBatch(): RocksDBWriteBatch {}
+
+    /**
+     * Begins a transaction on this database. Requires opening it with 'transactional' => true or 'optimistic'.
+     * @param array|null $options set_snapshot, commit_on_destruct, plus lock_timeout, deadlock_detect and expiration in pessimistic mode
+     * @return RocksDBTransaction
+     */
+    public function beginTransaction(?array $options = null): RocksDBTransaction {}
+
+    /**
+     * Creates a backup engine at the given path for this database.
+     * @param string $backup_path
+     * @param array|null $options see RocksDBBackup::init()
+     * @return RocksDBBackup
+     */
+    public function backupEngine(string $backup_path, ?array $options = null): RocksDBBackup {}
+
+    /**
+     * Creates an iterator over the database or a column family.
+     * Options: lower_bound, upper_bound, prefix and the read options accepted by get().
+     * @param string|null $cf_name
+     * @param array|null $options
+     * @return RocksDBIterator
+     */
+    public function iterator(?string $cf_name = null, ?array $options = null): RocksDBIterator {}
 
      /**
      * Moves the iterator to the first element.
@@ -165,81 +319,222 @@ class RocksDBBackup {
     /**
      * Initializes the backup engine with the specified path.
      * @param string $backup_path
+     * @param array|null $options max_background_operations
      * @return void
      */
-    public function init(string $backup_path) {}
+    public function init(string $backup_path, ?array $options = null) {}
 
     /**
      * Creates a backup of the database.
+     * @param bool|null $flush_before_backup flush the memtables first
+     * @param string|null $app_metadata stored with the backup and returned by info()
      * @return void
      */
-    public function create() {}
+    public function create(?bool $flush_before_backup = null, ?string $app_metadata = null) {}
 
     /**
-     * Returns information about the backups.
+     * Returns one entry per backup, oldest first, with backup_id, timestamp, size, num_files and app_metadata.
      * @return array
      */
     public function info(): array {}
 
+    /**
+     * Checks that all files of a backup exist and have the expected sizes.
+     * @param int $backup_id
+     * @return void
+     * @throws \RocksDB\Exception\RocksDBException if the backup is missing or damaged
+     */
+    public function verify(int $backup_id) {}
+
+    /**
+     * Deletes the oldest backup.
+     * @return void
+     * @throws \RocksDB\Exception\RocksDBException if there is no backup
+     */
+    public function deleteOldest() {}
+
     /**
      * Purges old backups, keeping the specified number of backups.
      * @param int $num_backups_to_keep
      * @return void
      */
-    public function purge_old(int $num_backups_to_keep) {}
+    public function purgeOld(int $num_backups_to_keep) {}
 
     /**
      * Restores the database from a backup.
      * @param int $backup_id
      * @param string $restore_path
+     * @param array|null $options wal_dir, keep_log_files
      * @return void
+     * @throws \RocksDB\Exception\RocksDBException if the database is open at $restore_path
      */
-    public function restore(int $backup_id, string $restore_path) {}
+    public function restore(int $backup_id, string $restore_path, ?array $options = null) {}
+
+    /**
+     * Restores the database from the newest backup.
+     * @param string $restore_path
+     * @param array|null $options wal_dir, keep_log_files
+     * @return void
+     * @throws \RocksDB\Exception\RocksDBException if the database is open at $restore_path
+     */
+    public function restoreLatest(string $restore_path, ?array $options = null) {}
 }
 
 class RocksDBSnapshot {
     /**
-     * Creates a new RocksDBSnapshot instance with the specified path and TTL.
-     * @param string $path
-     * @param int|null $ttl_secs
+     * Retrieves the value associated with the given key as of the snapshot.
+     * @param string $key
+     * @param string|null $cf_name
+     * @return string|null
      */
-    public function __construct(string $path, ?int $ttl_secs = null) {}
+    public function get(string $key, ?string $cf_name = null): ?string {}
 
     /**
-     * Creates a snapshot of the current state of the database.
-     * @return void
+     * Retrieves the values of several keys as of the snapshot.
+     * @param string[] $keys
+     * @param string|null $cf_name
+     * @return array
+     */
+    public function multiGet(array $keys, ?string $cf_name = null): array {}
+
+    /**
+     * Returns all key-value pairs visible in the snapshot.
+     * @param string|null $cf_name
+     * @return array
+     */
+    public function all(?string $cf_name = null): array {}
+
+    /**
+     * Returns all keys visible in the snapshot.
+     * @param string|null $cf_name
+     * @return string[]
+     */
+    public function keys(?string $cf_name = null): array {}
+
+    /**
+     * Creates an iterator that reads the database as of the snapshot.
+     * @param string|null $cf_name
+     * @param array|null $options
+     * @return RocksDBIterator
      */
-    public function create() {}
+    public function iterator(?string $cf_name = null, ?array $options = null): RocksDBIterator {}
 
     /**
-     * Releases the current snapshot.
+     * Releases the snapshot.
      * @return void
      */
     public function release() {}
 }
 
+class RocksDBIterator implements Iterator {
+    /**
+     * Moves to the first entry within the bounds.
+     * @return void
+     */
+    public function rewind(): void {}
+
+    /**
+     * Checks if the iterator is positioned on an entry.
+     * @return bool
+     */
+    public function valid(): bool {}
+
+    /**
+     * Returns the value at the current position.
+     * @return string|null
+     */
+    public function current(): ?string {}
+
+    /**
+     * Returns the key at the current position.
+     * @return string|null
+     */
+    public function key(): ?string {}
+
+    /**
+     * Returns the value at the current position.
+     * @return string|null
+     */
+    public function value(): ?string {}
+
+    /**
+     * Moves to the next entry.
+     * @return void
+     */
+    public function next(): void {}
+
+    /**
+     * Moves to the previous entry.
+     * @return void
+     */
+    public function prev(): void {}
+
+    /**
+     * Moves to the first entry within the bounds.
+     * @return void
+     */
+    public function seekToFirst(): void {}
+
+    /**
+     * Moves to the last entry within the bounds.
+     * @return void
+     */
+    public function seekToLast(): void {}
+
+    /**
+     * Moves to the first entry at or after the given key.
+     * @param string $key
+     * @return void
+     */
+    public function seek(string $key): void {}
+
+    /**
+     * Moves to the last entry at or before the given key.
+     * @param string $key
+     * @return void
+     */
+    public function seekForPrev(string $key): void {}
+
+    /**
+     * Throws if the iterator stopped because of an error.
+     * @return void
+     * @throws \RocksDB\Exception\RocksDBException
+     */
+    public function status(): void {}
+}
+
 class RocksDBTransaction {
     /**
-     * Creates a new RocksDBTransaction instance with the specified path and TTL.
+     * Creates a new RocksDBTransaction instance with the specified path and an active transaction.
      * @param string $path
-     * @param int|null $ttl_secs
+     * @param array|null $write_options Used when transactions commit: sync, disable_wal, no_slowdown, low_pri
+     * @param array|null $options Same as for RocksDB::beginTransaction(), including commit_on_destruct
      */
-    public function __construct(string $path, ?int $ttl_secs = null) {}
+    public function __construct(string $path, ?array $write_options = null, ?array $options = null) {}
 
     /**
-     * Starts a new transaction.
+     * Starts a new transaction after the previous one was committed or rolled back.
      * @return void
+     * @throws \RocksDB\Exception\RocksDBException if a transaction is already active
+     */
+    public function begin() {}
+
+    /**
+     * Whether a transaction is active.
+     * @return bool
      */
-    public function start() {}
+    public function isActive(): bool {}
 
     /**
-     * Commits the current transaction.
+     * Commits and ends the current transaction.
      * @return void
+     * @throws \RocksDB\Exception\ConflictException in optimistic mode, if another writer changed a key this transaction used
+     * @throws \RocksDB\Exception\RocksDBException if no transaction is active
      */
     public function commit() {}
 
     /**
-     * Rolls back the current transaction.
+     * Rolls back and ends the current transaction. Does nothing if none is active.
      * @return void
      */
     public function rollback() {}
@@ -248,13 +543,13 @@ class RocksDBTransaction {
      * Sets a savepoint within the current transaction.
      * @return void
      */
-    public function set_savepoint() {}
+    public function setSavepoint() {}
 
     /**
      * Rolls back the transaction to the last savepoint.
      * @return void
      */
-    public function rollback_to_savepoint() {}
+    public function rollbackToSavepoint() {}
 
     /**
      * Puts a key-value pair into the current transaction.
@@ -273,6 +568,26 @@ class RocksDBTransaction {
      */
     public function get(string $key, ?string $cf_name = null): ?string {}
 
+    /**
+     * Gets the value of a key and locks it until the transaction ends.
+     * @param string $key
+     * @param bool|null $exclusive false for a shared lock
+     * @param string|null $cf_name
+     * @return string|null
+     * @throws \RocksDB\Exception\LockTimeoutException if the lock can't be acquired in time
+     */
+    public function getForUpdate(string $key, ?bool $exclusive = true, ?string $cf_name = null): ?string {}
+
+    /**
+     * Gets and locks several keys, in the order given.
+     * @param array $keys
+     * @param bool|null $exclusive false for shared locks
+     * @param string|null $cf_name
+     * @return array values keyed by the requested keys, null for missing ones
+     * @throws \RocksDB\Exception\LockTimeoutException if a lock can't be acquired in time
+     */
+    public function multiGetForUpdate(array $keys, ?bool $exclusive = true, ?string $cf_name = null): array {}
+
     /**
      * Deletes a key-value pair within the current transaction.
      * @param string $key
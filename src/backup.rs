@@ -1,14 +1,166 @@
+use crate::handle::{with_common, DbHandle, OpenDb};
+use crate::options::{backup_engine_options, restore_options};
+use crate::persistent;
+use crate::RocksDBException;
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::error::Error;
 use ext_php_rs::prelude::*;
-use rust_rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+use ext_php_rs::types::{ZendHashTable, Zval};
+use rust_rocksdb::backup::BackupEngine;
 use rust_rocksdb::{Env, Options, DB};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+/// Application metadata is kept next to the backups, one file per backup id,
+/// as the bindings can't store it in the backup itself.
+const APP_METADATA_DIR: &str = "app_metadata";
+
+/// One backup as returned by `info()`.
+pub struct BackupInfo {
+    pub backup_id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+    pub app_metadata: Option<String>,
+}
+
+impl IntoZval for BackupInfo {
+    const TYPE: ext_php_rs::flags::DataType = ext_php_rs::flags::DataType::Array;
+
+    fn set_zval(self, zv: &mut Zval, _persistent: bool) -> Result<(), Error> {
+        let mut ht = ZendHashTable::new();
+        ht.insert("backup_id", self.backup_id as i64)?;
+        ht.insert("timestamp", self.timestamp)?;
+        ht.insert("size", self.size as i64)?;
+        ht.insert("num_files", self.num_files as i64)?;
+        ht.insert("app_metadata", self.app_metadata.into_zval(false)?)?;
+        zv.set_hashtable(ht);
+        Ok(())
+    }
+}
+
+/// A backup engine together with the directory it keeps its backups in.
+struct Engine {
+    engine: BackupEngine,
+    path: PathBuf,
+}
+
+impl Engine {
+    fn app_metadata(&self, backup_id: u32) -> Option<String> {
+        fs::read_to_string(self.path.join(APP_METADATA_DIR).join(backup_id.to_string())).ok()
+    }
+
+    /// Stores `app_metadata` for the newest backup. Without metadata, removes
+    /// any file left at its id by an earlier backup that was purged.
+    fn set_app_metadata(&self, app_metadata: Option<&str>) -> PhpResult<()> {
+        let backup_id = self
+            .engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id)
+            .max()
+            .ok_or_else(|| {
+                PhpException::from_class::<RocksDBException>(
+                    "No backup to attach metadata to".to_string(),
+                )
+            })?;
+        let dir = self.path.join(APP_METADATA_DIR);
+        let file = dir.join(backup_id.to_string());
+        match app_metadata {
+            Some(app_metadata) => {
+                fs::create_dir_all(&dir).and_then(|_| fs::write(&file, app_metadata))
+            }
+            None => match fs::remove_file(&file) {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        }
+        .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+    }
+
+    /// Removes the metadata of backups that no longer exist.
+    fn prune_app_metadata(&self) {
+        let backup_ids: Vec<String> = self
+            .engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id.to_string())
+            .collect();
+        if let Ok(entries) = fs::read_dir(self.path.join(APP_METADATA_DIR)) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if !backup_ids
+                    .iter()
+                    .any(|id| name.to_str() == Some(id.as_str()))
+                {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+}
+
 #[php_class]
 pub struct RocksDBBackup {
-    db: Arc<DB>,
-    backup_engine: Mutex<Option<BackupEngine>>,
+    db: Arc<OpenDb>,
+    backup_engine: Mutex<Option<Engine>>,
+}
+
+fn open_engine(backup_path: &str, options: Option<&ZendHashTable>) -> PhpResult<Engine> {
+    let be_opts = backup_engine_options(backup_path, options)?;
+    let env = Env::new().map_err(|e| e.to_string())?;
+    let engine = BackupEngine::open(&be_opts, &env).map_err(|e| e.to_string())?;
+    Ok(Engine {
+        engine,
+        path: PathBuf::from(backup_path),
+    })
+}
+
+fn not_initialized() -> PhpException {
+    PhpException::from_class::<RocksDBException>("Backup engine is not initialized".to_string())
+}
+
+impl RocksDBBackup {
+    /// Creates a backup engine at `backup_path` for an already open database.
+    pub fn new(
+        db: Arc<OpenDb>,
+        backup_path: &str,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<Self> {
+        Ok(RocksDBBackup {
+            db,
+            backup_engine: Mutex::new(Some(open_engine(backup_path, options)?)),
+        })
+    }
+
+    /// Refuses to restore into the directory of a database this process has open.
+    fn check_restore_target(&self, dir: &str) -> PhpResult<()> {
+        let open_here = match (fs::canonicalize(self.db.path()), fs::canonicalize(dir)) {
+            (Ok(open), Ok(target)) => open == target,
+            _ => false,
+        };
+        if open_here || persistent::is_open(dir) {
+            return Err(PhpException::from_class::<RocksDBException>(format!(
+                "Cannot restore into '{}' while the database is open there",
+                dir
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks both restore targets and takes the lock file of `dir`, so no other
+    /// `RocksDB` instance or process can open it during the restore.
+    ///
+    /// Throws a `LockTimeoutException` right away if the lock file is held.
+    fn lock_restore_target(&self, dir: &str, wal_dir: &str) -> PhpResult<File> {
+        self.check_restore_target(dir)?;
+        self.check_restore_target(wal_dir)?;
+        crate::acquire_lock(&format!("{}-php.lock", dir), Some(Duration::ZERO))
+    }
 }
 
 #[php_impl]
@@ -30,61 +182,128 @@ impl RocksDBBackup {
 
         match db {
             Ok(db) => Ok(RocksDBBackup {
-                db: Arc::new(db),
+                db: Arc::new(OpenDb::new(DbHandle::Plain(db), BTreeMap::new(), None)),
                 backup_engine: Mutex::new(None),
             }),
             Err(e) => Err(e.to_string().into()),
         }
     }
 
-    pub fn init(&self, backup_path: String) -> PhpResult<()> {
+    pub fn init(&self, backup_path: String, options: Option<&ZendHashTable>) -> PhpResult<()> {
         let mut backup_engine = self.backup_engine.lock().unwrap();
-        let be_opts = BackupEngineOptions::new(&backup_path).map_err(|e| e.to_string())?;
-        *backup_engine = Some(
-            BackupEngine::open(&be_opts, &Env::new().map_err(|e| e.to_string())?)
-                .map_err(|e| e.to_string())?,
-        );
+        *backup_engine = Some(open_engine(&backup_path, options)?);
         Ok(())
     }
 
-    pub fn create(&self) -> PhpResult<()> {
+    pub fn create(
+        &self,
+        flush_before_backup: Option<bool>,
+        app_metadata: Option<String>,
+    ) -> PhpResult<()> {
         let mut backup_engine = self.backup_engine.lock().unwrap();
         if let Some(be) = backup_engine.as_mut() {
-            be.create_new_backup(&*self.db).map_err(|e| e.to_string())?;
+            let flush_before_backup = flush_before_backup.unwrap_or(false);
+            with_common!(&self.db.db, "create", |db| be
+                .engine
+                .create_new_backup_flush(db, flush_before_backup)
+                .map_err(|e| PhpException::from(e.to_string())))?;
+            be.set_app_metadata(app_metadata.as_deref())?;
         }
         Ok(())
     }
 
-    pub fn info(&self) -> PhpResult<HashMap<String, i64>> {
+    /// Returns every backup, oldest first.
+    pub fn info(&self) -> PhpResult<Vec<BackupInfo>> {
         let backup_engine = self.backup_engine.lock().unwrap();
-        if let Some(be) = &*backup_engine {
-            let backup_info = be.get_backup_info();
-            let mut result = HashMap::new();
-            for info in backup_info {
-                result.insert("backup_id".to_string(), info.backup_id as i64);
-                result.insert("timestamp".to_string(), info.timestamp as i64);
-                result.insert("size".to_string(), info.size as i64);
-                result.insert("num_files".to_string(), info.num_files as i64);
-            }
-            return Ok(result);
+        let be = backup_engine.as_ref().ok_or_else(not_initialized)?;
+        // Another engine may have purged backups without removing their metadata.
+        be.prune_app_metadata();
+        Ok(be
+            .engine
+            .get_backup_info()
+            .into_iter()
+            .map(|info| BackupInfo {
+                backup_id: info.backup_id,
+                timestamp: info.timestamp,
+                size: info.size,
+                num_files: info.num_files,
+                app_metadata: be.app_metadata(info.backup_id),
+            })
+            .collect())
+    }
+
+    /// Checks that the files of a backup are all present and have the expected sizes.
+    pub fn verify(&self, backup_id: u32) -> PhpResult<()> {
+        let backup_engine = self.backup_engine.lock().unwrap();
+        let be = backup_engine.as_ref().ok_or_else(not_initialized)?;
+        be.engine
+            .verify_backup(backup_id)
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+    }
+
+    /// Deletes the oldest backup.
+    ///
+    /// The bindings can only drop backups oldest first, so there is no way to
+    /// delete an arbitrary backup by id.
+    pub fn delete_oldest(&self) -> PhpResult<()> {
+        let mut backup_engine = self.backup_engine.lock().unwrap();
+        let be = backup_engine.as_mut().ok_or_else(not_initialized)?;
+        let backups = be.engine.get_backup_info().len();
+        if backups == 0 {
+            return Err(PhpException::from_class::<RocksDBException>(
+                "No backup to delete".to_string(),
+            ));
         }
-        Err("Backup engine is not initialized".into())
+        be.engine
+            .purge_old_backups(backups - 1)
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+        be.prune_app_metadata();
+        Ok(())
     }
 
     pub fn purge_old(&self, num_backups_to_keep: usize) -> PhpResult<()> {
         let mut backup_engine = self.backup_engine.lock().unwrap();
         if let Some(be) = backup_engine.as_mut() {
-            be.purge_old_backups(num_backups_to_keep)
+            be.engine
+                .purge_old_backups(num_backups_to_keep)
                 .map_err(|e| e.to_string())?;
+            be.prune_app_metadata();
         }
         Ok(())
     }
 
-    pub fn restore(&self, backup_id: u32, restore_path: String) -> PhpResult<()> {
+    pub fn restore(
+        &self,
+        backup_id: u32,
+        restore_path: String,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        let settings = restore_options(options)?;
+        let wal_dir = settings.wal_dir.as_deref().unwrap_or(&restore_path);
+        let _lock = self.lock_restore_target(&restore_path, wal_dir)?;
+
+        let mut backup_engine = self.backup_engine.lock().unwrap();
+        if let Some(be) = backup_engine.as_mut() {
+            be.engine
+                .restore_from_backup(&restore_path, wal_dir, &settings.options, backup_id)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn restore_latest(
+        &self,
+        restore_path: String,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        let settings = restore_options(options)?;
+        let wal_dir = settings.wal_dir.as_deref().unwrap_or(&restore_path);
+        let _lock = self.lock_restore_target(&restore_path, wal_dir)?;
+
         let mut backup_engine = self.backup_engine.lock().unwrap();
         if let Some(be) = backup_engine.as_mut() {
-            let opts = RestoreOptions::default();
-            be.restore_from_backup(&restore_path, &restore_path, &opts, backup_id)
+            be.engine
+                .restore_from_latest_backup(&restore_path, wal_dir, &settings.options)
                 .map_err(|e| e.to_string())?;
         }
         Ok(())
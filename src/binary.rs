@@ -0,0 +1,63 @@
+use ext_php_rs::convert::IntoZval;
+use ext_php_rs::error::Error;
+use ext_php_rs::ffi::zend_hash_str_update;
+use ext_php_rs::flags::DataType;
+use ext_php_rs::types::{ZendHashTable, Zval};
+
+/// An ordered list of entries returned to PHP as an associative array.
+///
+/// `ZendHashTable::insert` only accepts `&str` keys, so keys are inserted as
+/// raw bytes to keep non-UTF-8 keys (and keys containing NUL) intact. Keys that
+/// PHP itself would turn into integers, like `"42"`, are inserted as integers so
+/// `$result["42"]` finds them.
+pub struct BinaryMap<V: IntoZval>(pub Vec<(Vec<u8>, V)>);
+
+impl<V: IntoZval> IntoZval for BinaryMap<V> {
+    const TYPE: DataType = DataType::Array;
+
+    fn set_zval(self, zv: &mut Zval, _persistent: bool) -> Result<(), Error> {
+        let mut ht = ZendHashTable::with_capacity(self.0.len() as u32);
+        for (key, value) in self.0 {
+            if let Some(index) = integer_key(&key) {
+                ht.insert_at_index(index as u64, value)?;
+                continue;
+            }
+            let mut value = value.into_zval(false)?;
+            unsafe {
+                zend_hash_str_update(&mut *ht, key.as_ptr().cast(), key.len() as _, &mut value);
+            }
+            // The hash table now owns the value.
+            std::mem::forget(value);
+        }
+        zv.set_hashtable(ht);
+        Ok(())
+    }
+}
+
+/// Returns the integer PHP would use for the array key `key`, mirroring
+/// `ZEND_HANDLE_NUMERIC_STR`: canonical decimal integers without a plus sign,
+/// leading zeros or `-0` that fit in a `zend_long`.
+fn integer_key(key: &[u8]) -> Option<i64> {
+    let digits = key.strip_prefix(b"-").unwrap_or(key);
+    match digits {
+        [] => return None,
+        [b'0'] if digits.len() < key.len() => return None,
+        [b'0', _, ..] => return None,
+        _ if !digits.iter().all(u8::is_ascii_digit) => return None,
+        _ => {}
+    }
+    std::str::from_utf8(key).ok()?.parse().ok()
+}
+
+/// Returns the smallest key that sorts after every key starting with `prefix`,
+/// or `None` if there is no such key (the prefix is empty or all `0xff`).
+pub fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
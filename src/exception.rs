@@ -0,0 +1,57 @@
+use crate::RocksDBException;
+use ext_php_rs::builders::ClassBuilder;
+use ext_php_rs::class::RegisteredClass;
+use ext_php_rs::exception::PhpException;
+use ext_php_rs::zend::ClassEntry;
+use rust_rocksdb::{Error, ErrorKind};
+
+pub const LOCK_TIMEOUT: &str = "RocksDB\\Exception\\LockTimeoutException";
+pub const CLOSED: &str = "RocksDB\\Exception\\ClosedException";
+pub const DEADLOCK: &str = "RocksDB\\Exception\\DeadlockException";
+pub const BUSY: &str = "RocksDB\\Exception\\BusyException";
+pub const CONFLICT: &str = "RocksDB\\Exception\\ConflictException";
+
+/// Subclasses of `RocksDBException` thrown for specific failures.
+///
+/// `#[php_class]` registers classes in no particular order, so these carry no
+/// Rust state and are registered at startup, once their parent exists.
+const SUBCLASSES: &[&str] = &[LOCK_TIMEOUT, CLOSED, DEADLOCK, BUSY, CONFLICT];
+
+pub fn register() -> ext_php_rs::error::Result<()> {
+    let parent = RocksDBException::get_metadata().ce();
+    for name in SUBCLASSES {
+        ClassBuilder::new(*name).extends(parent).build()?;
+    }
+    Ok(())
+}
+
+/// Creates an exception of the named subclass, or a plain `RocksDBException`
+/// if the subclass is not registered.
+pub fn exception(class: &str, message: String) -> PhpException {
+    match ClassEntry::try_find(class) {
+        Some(ce) => PhpException::new(message, 0, ce),
+        None => PhpException::from_class::<RocksDBException>(message),
+    }
+}
+
+/// Converts a RocksDB error into an exception whose class tells lock
+/// timeouts, deadlocks and other contention apart, so callers can retry.
+pub fn from_error(error: Error) -> PhpException {
+    let message = error.to_string();
+    let class = match error.kind() {
+        ErrorKind::TimedOut if message.contains("Timeout waiting to lock key") => LOCK_TIMEOUT,
+        ErrorKind::Busy if message.contains("Deadlock") => DEADLOCK,
+        ErrorKind::Busy | ErrorKind::TryAgain => BUSY,
+        _ => return PhpException::from_class::<RocksDBException>(message),
+    };
+    exception(class, message)
+}
+
+/// Like `from_error`, for committing an optimistic transaction: `Busy` and
+/// `TryAgain` mean another writer changed a key the transaction used.
+pub fn from_commit_error(error: Error) -> PhpException {
+    match error.kind() {
+        ErrorKind::Busy | ErrorKind::TryAgain => exception(CONFLICT, error.to_string()),
+        _ => from_error(error),
+    }
+}
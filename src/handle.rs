@@ -0,0 +1,491 @@
+use crate::exception;
+use crate::options::TransactionSettings;
+use crate::RocksDBException;
+use ext_php_rs::prelude::*;
+use rust_rocksdb::{
+    AsColumnFamilyRef, BoundColumnFamily, DBAccess, DBRawIteratorWithThreadMode, Error,
+    OptimisticTransactionDB, Options, ReadOptions, SnapshotWithThreadMode, Transaction,
+    TransactionDB, WriteBatchWithTransaction, WriteOptions, DB,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The open database behind a `RocksDB` object.
+pub enum DbHandle {
+    Plain(DB),
+    Transactional(TransactionDB),
+    Optimistic(OptimisticTransactionDB),
+    ReadOnly(DB),
+    Secondary(DB),
+}
+
+/// An open database together with the `-php.lock` file guarding its path.
+///
+/// Shared through an `Arc` with the iterators, snapshots, write batches,
+/// transactions and backup engines created from it, so they all work on the
+/// same handle instead of opening the path again, and the lock is only
+/// released once the last of them is gone.
+pub struct OpenDb {
+    pub db: DbHandle,
+    /// The options each column family was opened or created with.
+    cf_options: Mutex<BTreeMap<String, Options>>,
+    // Declared after `db`, so the database is closed before the lock is released.
+    _lock: Option<File>,
+}
+
+impl OpenDb {
+    /// Wraps `db`, holding `lock` until the database is closed. Read-only and
+    /// secondary instances, and the standalone class constructors, take no lock.
+    pub fn new(db: DbHandle, cf_options: BTreeMap<String, Options>, lock: Option<File>) -> Self {
+        OpenDb {
+            db,
+            cf_options: Mutex::new(cf_options),
+            _lock: lock,
+        }
+    }
+
+    /// The options column family `name` was opened or created with, if known.
+    pub fn cf_options(&self, name: &str) -> Option<Options> {
+        self.cf_options.lock().unwrap().get(name).cloned()
+    }
+
+    /// Records the options of a new column family, or forgets those of a dropped one.
+    pub fn set_cf_options(&self, name: &str, cf_options: Option<Options>) {
+        let mut all = self.cf_options.lock().unwrap();
+        match cf_options {
+            Some(cf_options) => all.insert(name.to_string(), cf_options),
+            None => all.remove(name),
+        };
+    }
+}
+
+impl Deref for OpenDb {
+    type Target = DbHandle;
+
+    fn deref(&self) -> &DbHandle {
+        &self.db
+    }
+}
+
+/// Runs `$body` with `$db` bound to whichever database the handle holds.
+macro_rules! with_db {
+    ($handle:expr, |$db:ident| $body:expr) => {
+        match $handle {
+            $crate::handle::DbHandle::Plain($db) => $body,
+            $crate::handle::DbHandle::Transactional($db) => $body,
+            $crate::handle::DbHandle::Optimistic($db) => $body,
+            $crate::handle::DbHandle::ReadOnly($db) => $body,
+            $crate::handle::DbHandle::Secondary($db) => $body,
+        }
+    };
+}
+
+/// Runs `$body` with `$db` bound to the database if it supports the full
+/// `DBCommon` API, and fails with an exception naming `$op` otherwise.
+macro_rules! with_common {
+    ($handle:expr, $op:expr, |$db:ident| $body:expr) => {
+        match $handle {
+            $crate::handle::DbHandle::Plain($db)
+            | $crate::handle::DbHandle::ReadOnly($db)
+            | $crate::handle::DbHandle::Secondary($db) => $body,
+            $crate::handle::DbHandle::Optimistic($db) => $body,
+            $crate::handle::DbHandle::Transactional(_) => {
+                Err($crate::handle::unsupported($op, "transactional"))
+            }
+        }
+    };
+}
+
+pub(crate) use {with_common, with_db};
+
+pub fn unsupported(op: &str, mode: &str) -> PhpException {
+    PhpException::from_class::<RocksDBException>(format!(
+        "{}() is not supported in {} mode",
+        op, mode
+    ))
+}
+
+/// A raw iterator over any kind of database.
+pub trait RawCursor {
+    fn valid(&self) -> bool;
+    fn key(&self) -> Option<&[u8]>;
+    fn value(&self) -> Option<&[u8]>;
+    fn seek_to_first(&mut self);
+    fn seek_to_last(&mut self);
+    fn seek(&mut self, key: &[u8]);
+    fn seek_for_prev(&mut self, key: &[u8]);
+    fn next(&mut self);
+    fn prev(&mut self);
+    fn status(&self) -> Result<(), Error>;
+}
+
+impl<D: DBAccess> RawCursor for DBRawIteratorWithThreadMode<'_, D> {
+    fn valid(&self) -> bool {
+        DBRawIteratorWithThreadMode::valid(self)
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        DBRawIteratorWithThreadMode::key(self)
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        DBRawIteratorWithThreadMode::value(self)
+    }
+
+    fn seek_to_first(&mut self) {
+        DBRawIteratorWithThreadMode::seek_to_first(self)
+    }
+
+    fn seek_to_last(&mut self) {
+        DBRawIteratorWithThreadMode::seek_to_last(self)
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        DBRawIteratorWithThreadMode::seek(self, key)
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        DBRawIteratorWithThreadMode::seek_for_prev(self, key)
+    }
+
+    fn next(&mut self) {
+        DBRawIteratorWithThreadMode::next(self)
+    }
+
+    fn prev(&mut self) {
+        DBRawIteratorWithThreadMode::prev(self)
+    }
+
+    fn status(&self) -> Result<(), Error> {
+        DBRawIteratorWithThreadMode::status(self)
+    }
+}
+
+/// A snapshot of any kind of database.
+pub trait SnapshotRef {
+    /// Makes reads through `readopts` see the database as of this snapshot.
+    fn apply(&self, readopts: &mut ReadOptions);
+}
+
+impl<D: DBAccess> SnapshotRef for SnapshotWithThreadMode<'_, D> {
+    fn apply(&self, readopts: &mut ReadOptions) {
+        readopts.set_snapshot(self);
+    }
+}
+
+/// A write batch matching the kind of database it will be written to.
+pub enum Batch {
+    Plain(WriteBatchWithTransaction<false>),
+    Transactional(WriteBatchWithTransaction<true>),
+}
+
+impl Batch {
+    pub fn put_cf(&mut self, cf: &impl AsColumnFamilyRef, key: &[u8], value: &[u8]) {
+        match self {
+            Batch::Plain(batch) => batch.put_cf(cf, key, value),
+            Batch::Transactional(batch) => batch.put_cf(cf, key, value),
+        }
+    }
+
+    pub fn merge_cf(&mut self, cf: &impl AsColumnFamilyRef, key: &[u8], value: &[u8]) {
+        match self {
+            Batch::Plain(batch) => batch.merge_cf(cf, key, value),
+            Batch::Transactional(batch) => batch.merge_cf(cf, key, value),
+        }
+    }
+
+    pub fn delete_cf(&mut self, cf: &impl AsColumnFamilyRef, key: &[u8]) {
+        match self {
+            Batch::Plain(batch) => batch.delete_cf(cf, key),
+            Batch::Transactional(batch) => batch.delete_cf(cf, key),
+        }
+    }
+
+    pub fn delete_range_cf(
+        &mut self,
+        cf: &impl AsColumnFamilyRef,
+        from: &[u8],
+        to: &[u8],
+    ) -> PhpResult<()> {
+        match self {
+            Batch::Plain(batch) => {
+                batch.delete_range_cf(cf, from, to);
+                Ok(())
+            }
+            Batch::Transactional(_) => Err(unsupported("deleteRange", "transactional")),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Batch::Plain(batch) => batch.clear(),
+            Batch::Transactional(batch) => batch.clear(),
+        }
+    }
+}
+
+/// A transaction on either kind of transactional database.
+pub enum Txn<'a> {
+    Pessimistic(Transaction<'a, TransactionDB>),
+    Optimistic(Transaction<'a, OptimisticTransactionDB>),
+}
+
+/// Runs `$body` with `$txn` bound to the transaction, whatever its kind.
+macro_rules! with_txn {
+    ($txn:expr, |$t:ident| $body:expr) => {
+        match $txn {
+            Txn::Pessimistic($t) => $body,
+            Txn::Optimistic($t) => $body,
+        }
+    };
+}
+
+impl Txn<'_> {
+    /// Commits the transaction. An optimistic transaction fails with a
+    /// `ConflictException` if another writer changed a key it read or wrote.
+    pub fn commit(self) -> PhpResult<()> {
+        match self {
+            Txn::Pessimistic(txn) => txn.commit().map_err(exception::from_error),
+            Txn::Optimistic(txn) => txn.commit().map_err(exception::from_commit_error),
+        }
+    }
+
+    pub fn rollback(&self) -> Result<(), Error> {
+        with_txn!(self, |txn| txn.rollback())
+    }
+
+    pub fn set_savepoint(&self) {
+        with_txn!(self, |txn| txn.set_savepoint())
+    }
+
+    pub fn rollback_to_savepoint(&self) -> Result<(), Error> {
+        with_txn!(self, |txn| txn.rollback_to_savepoint())
+    }
+
+    pub fn get_cf(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        with_txn!(self, |txn| txn.get_cf(cf, key))
+    }
+
+    pub fn get_for_update_cf(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        exclusive: bool,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        with_txn!(self, |txn| txn.get_for_update_cf(cf, key, exclusive))
+    }
+
+    pub fn put_cf(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        with_txn!(self, |txn| txn.put_cf(cf, key, value))
+    }
+
+    pub fn merge_cf(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        with_txn!(self, |txn| txn.merge_cf(cf, key, value))
+    }
+
+    pub fn delete_cf(&self, cf: &impl AsColumnFamilyRef, key: &[u8]) -> Result<(), Error> {
+        with_txn!(self, |txn| txn.delete_cf(cf, key))
+    }
+}
+
+impl DbHandle {
+    /// How the database was opened, as used in error messages.
+    pub fn mode(&self) -> &'static str {
+        match self {
+            DbHandle::Plain(_) => "read-write",
+            DbHandle::Transactional(_) => "transactional",
+            DbHandle::Optimistic(_) => "optimistic",
+            DbHandle::ReadOnly(_) => "read-only",
+            DbHandle::Secondary(_) => "secondary",
+        }
+    }
+
+    /// Fails with an exception naming `op` if the database was opened for reading only.
+    pub fn check_writable(&self, op: &str) -> PhpResult<()> {
+        match self {
+            DbHandle::ReadOnly(_) | DbHandle::Secondary(_) => Err(unsupported(op, self.mode())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        with_db!(self, |db| db.path())
+    }
+
+    pub fn cf_handle(&self, name: &str) -> Option<Arc<BoundColumnFamily<'_>>> {
+        with_db!(self, |db| db.cf_handle(name))
+    }
+
+    pub fn create_cf(&self, name: &str, opts: &Options) -> Result<(), Error> {
+        with_db!(self, |db| db.create_cf(name, opts))
+    }
+
+    pub fn drop_cf(&self, name: &str) -> Result<(), Error> {
+        with_db!(self, |db| db.drop_cf(name))
+    }
+
+    pub fn get_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        readopts: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        with_db!(self, |db| db.get_cf_opt(cf, key, readopts))
+    }
+
+    pub fn put_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        value: &[u8],
+        writeopts: &WriteOptions,
+    ) -> Result<(), Error> {
+        with_db!(self, |db| db.put_cf_opt(cf, key, value, writeopts))
+    }
+
+    pub fn merge_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        value: &[u8],
+        writeopts: &WriteOptions,
+    ) -> Result<(), Error> {
+        with_db!(self, |db| db.merge_cf_opt(cf, key, value, writeopts))
+    }
+
+    pub fn delete_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        key: &[u8],
+        writeopts: &WriteOptions,
+    ) -> Result<(), Error> {
+        with_db!(self, |db| db.delete_cf_opt(cf, key, writeopts))
+    }
+
+    pub fn multi_get_cf_opt<'a, 'b: 'a, K, I, W>(
+        &'a self,
+        keys: I,
+        readopts: &ReadOptions,
+    ) -> Vec<Result<Option<Vec<u8>>, Error>>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (&'b W, K)>,
+        W: 'b + AsColumnFamilyRef,
+    {
+        with_db!(self, |db| db.multi_get_cf_opt(keys, readopts))
+    }
+
+    /// Looks up several keys of one column family, batched where the database supports it.
+    pub fn batched_multi_get_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        keys: &[&[u8]],
+        readopts: &ReadOptions,
+    ) -> Vec<Result<Option<Vec<u8>>, Error>> {
+        match self {
+            DbHandle::Plain(db) | DbHandle::ReadOnly(db) | DbHandle::Secondary(db) => db
+                .batched_multi_get_cf_opt(cf, keys.iter().copied(), false, readopts)
+                .into_iter()
+                .map(|value| value.map(|value| value.map(|value| value.to_vec())))
+                .collect(),
+            DbHandle::Optimistic(db) => db
+                .batched_multi_get_cf_opt(cf, keys.iter().copied(), false, readopts)
+                .into_iter()
+                .map(|value| value.map(|value| value.map(|value| value.to_vec())))
+                .collect(),
+            DbHandle::Transactional(db) => {
+                db.multi_get_cf_opt(keys.iter().map(|key| (cf, *key)), readopts)
+            }
+        }
+    }
+
+    pub fn raw_iterator_cf_opt(
+        &self,
+        cf: &impl AsColumnFamilyRef,
+        readopts: ReadOptions,
+    ) -> Box<dyn RawCursor + '_> {
+        with_db!(self, |db| Box::new(db.raw_iterator_cf_opt(cf, readopts))
+            as Box<dyn RawCursor + '_>)
+    }
+
+    pub fn snapshot(&self) -> Box<dyn SnapshotRef + '_> {
+        with_db!(self, |db| Box::new(db.snapshot())
+            as Box<dyn SnapshotRef + '_>)
+    }
+
+    /// Starts an empty write batch for this database.
+    pub fn batch(&self) -> Batch {
+        match self {
+            DbHandle::Plain(_) | DbHandle::ReadOnly(_) | DbHandle::Secondary(_) => {
+                Batch::Plain(WriteBatchWithTransaction::default())
+            }
+            DbHandle::Transactional(_) | DbHandle::Optimistic(_) => {
+                Batch::Transactional(WriteBatchWithTransaction::default())
+            }
+        }
+    }
+
+    pub fn write_opt(&self, batch: Batch, writeopts: &WriteOptions) -> PhpResult<()> {
+        self.check_writable("write")?;
+        match (self, batch) {
+            (DbHandle::Plain(db), Batch::Plain(batch)) => db.write_opt(batch, writeopts),
+            (DbHandle::Transactional(db), Batch::Transactional(batch)) => {
+                db.write_opt(batch, writeopts)
+            }
+            (DbHandle::Optimistic(db), Batch::Transactional(batch)) => {
+                db.write_opt(batch, writeopts)
+            }
+            _ => {
+                return Err(PhpException::from_class::<RocksDBException>(
+                    "Write batch was created for another database".to_string(),
+                ))
+            }
+        }
+        .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+    }
+
+    /// Begins a transaction of the kind the database was opened for.
+    pub fn transaction(
+        &self,
+        writeopts: &WriteOptions,
+        settings: &TransactionSettings,
+    ) -> PhpResult<Txn<'_>> {
+        match self {
+            DbHandle::Transactional(db) => Ok(Txn::Pessimistic(
+                db.transaction_opt(writeopts, &settings.pessimistic),
+            )),
+            DbHandle::Optimistic(db) => {
+                if let Some(key) = &settings.lock_option {
+                    return Err(PhpException::from_class::<RocksDBException>(format!(
+                        "Option '{}' is not supported in optimistic mode",
+                        key
+                    )));
+                }
+                Ok(Txn::Optimistic(
+                    db.transaction_opt(writeopts, &settings.optimistic),
+                ))
+            }
+            _ => Err(PhpException::from_class::<RocksDBException>(
+                "Transactions require opening the database with 'transactional' => true"
+                    .to_string(),
+            )),
+        }
+    }
+}
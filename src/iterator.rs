@@ -0,0 +1,187 @@
+use crate::handle::{OpenDb, RawCursor};
+use crate::options::iterator_options;
+use crate::snapshot::SharedSnapshot;
+use crate::RocksDBException;
+use ext_php_rs::binary::Binary;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::ZendHashTable;
+use ext_php_rs::zend::ce;
+use rust_rocksdb::{Error, ReadOptions, DEFAULT_COLUMN_FAMILY_NAME};
+use self_cell::self_cell;
+use std::sync::Arc;
+
+/// Everything a cursor reads from: the handle, and the snapshot its reads see, if any.
+struct CursorSource {
+    db: Arc<OpenDb>,
+    _snapshot: Option<Arc<SharedSnapshot>>,
+}
+
+/// A raw iterator borrowing a handle for `'db`.
+type CursorBox<'db> = Box<dyn RawCursor + 'db>;
+
+self_cell!(
+    struct OwnedCursor {
+        owner: CursorSource,
+
+        #[covariant]
+        dependent: CursorBox,
+    }
+);
+
+/// A raw iterator kept alive together with everything it reads from.
+///
+/// The iterator is always dropped before the snapshot and the handle.
+pub struct Cursor(OwnedCursor);
+
+fn open_cursor<'a>(
+    source: &'a CursorSource,
+    cf_name: Option<&str>,
+    readopts: ReadOptions,
+) -> PhpResult<CursorBox<'a>> {
+    let cf = source
+        .db
+        .cf_handle(cf_name.unwrap_or(DEFAULT_COLUMN_FAMILY_NAME))
+        .ok_or("Column family not found")?;
+    Ok(source.db.raw_iterator_cf_opt(&cf, readopts))
+}
+
+impl Cursor {
+    pub fn new(
+        db: Arc<OpenDb>,
+        snapshot: Option<Arc<SharedSnapshot>>,
+        cf_name: Option<&str>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<Self> {
+        let mut readopts = iterator_options(options)?;
+        if let Some(snapshot) = &snapshot {
+            snapshot.apply(&mut readopts);
+        }
+
+        let source = CursorSource {
+            db,
+            _snapshot: snapshot,
+        };
+        let cursor = OwnedCursor::try_new(source, |source| open_cursor(source, cf_name, readopts))?;
+        Ok(Cursor(cursor))
+    }
+}
+
+impl RawCursor for Cursor {
+    fn valid(&self) -> bool {
+        self.0.borrow_dependent().valid()
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.0.borrow_dependent().key()
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        self.0.borrow_dependent().value()
+    }
+
+    fn seek_to_first(&mut self) {
+        self.0.with_dependent_mut(|_, iter| iter.seek_to_first())
+    }
+
+    fn seek_to_last(&mut self) {
+        self.0.with_dependent_mut(|_, iter| iter.seek_to_last())
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.0.with_dependent_mut(|_, iter| iter.seek(key))
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.0.with_dependent_mut(|_, iter| iter.seek_for_prev(key))
+    }
+
+    fn next(&mut self) {
+        self.0.with_dependent_mut(|_, iter| iter.next())
+    }
+
+    fn prev(&mut self) {
+        self.0.with_dependent_mut(|_, iter| iter.prev())
+    }
+
+    fn status(&self) -> Result<(), Error> {
+        self.0.borrow_dependent().status()
+    }
+}
+
+#[php_class]
+#[implements(ce::iterator())]
+pub struct RocksDBIterator {
+    cursor: Cursor,
+}
+
+impl RocksDBIterator {
+    pub fn new(
+        db: Arc<OpenDb>,
+        snapshot: Option<Arc<SharedSnapshot>>,
+        cf_name: Option<&str>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<Self> {
+        Ok(RocksDBIterator {
+            cursor: Cursor::new(db, snapshot, cf_name, options)?,
+        })
+    }
+}
+
+#[php_impl]
+impl RocksDBIterator {
+    pub fn rewind(&mut self) {
+        self.cursor.seek_to_first();
+    }
+
+    pub fn valid(&self) -> bool {
+        self.cursor.valid()
+    }
+
+    pub fn current(&self) -> Option<Binary<u8>> {
+        self.value()
+    }
+
+    pub fn key(&self) -> Option<Binary<u8>> {
+        self.cursor.key().map(|key| Binary::from(key.to_vec()))
+    }
+
+    pub fn value(&self) -> Option<Binary<u8>> {
+        self.cursor
+            .value()
+            .map(|value| Binary::from(value.to_vec()))
+    }
+
+    pub fn next(&mut self) {
+        if self.cursor.valid() {
+            self.cursor.next();
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if self.cursor.valid() {
+            self.cursor.prev();
+        }
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.cursor.seek_to_first();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.cursor.seek_to_last();
+    }
+
+    pub fn seek(&mut self, key: Binary<u8>) {
+        self.cursor.seek(key.as_slice());
+    }
+
+    pub fn seek_for_prev(&mut self, key: Binary<u8>) {
+        self.cursor.seek_for_prev(key.as_slice());
+    }
+
+    pub fn status(&self) -> PhpResult<()> {
+        self.cursor
+            .status()
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+    }
+}
@@ -1,9 +1,18 @@
 #![cfg_attr(all(windows, target_arch = "x86_64"), feature(abi_vectorcall))]
 
 mod backup;
+mod binary;
+mod exception;
+mod handle;
+mod iterator;
+mod merge;
+mod options;
+mod persistent;
+mod snapshot;
 mod transaction;
 mod write_batch;
 
+use ext_php_rs::binary::Binary;
 use ext_php_rs::convert::IntoZval;
 use ext_php_rs::error::Error;
 use ext_php_rs::prelude::*;
@@ -11,18 +20,30 @@ use ext_php_rs::types::{ZendHashTable, Zval};
 use ext_php_rs::zend::{ce, ModuleEntry};
 use ext_php_rs::{info_table_end, info_table_row, info_table_start};
 use fs2::FileExt;
-use json_patch::Patch;
+use rust_rocksdb::checkpoint::Checkpoint;
 use rust_rocksdb::{
-    ColumnFamilyDescriptor, DBWithThreadMode, MergeOperands, Options, SingleThreaded, DB,
+    BoundColumnFamily, OptimisticTransactionDB, Options, ReadOptions, SstFileWriter, TransactionDB,
+    WriteOptions, DB, DEFAULT_COLUMN_FAMILY_NAME,
 };
-use serde_json::{from_value, Value};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{Read, Seek, Write};
 use std::path::Path;
+use std::process;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::backup::RocksDBBackup;
+use crate::binary::{prefix_successor, BinaryMap};
+use crate::handle::{with_common, DbHandle, OpenDb, RawCursor};
+use crate::iterator::{Cursor, RocksDBIterator};
+use crate::options::{
+    cf_descriptors, db_options, parse_compression, transaction_options, ColumnFamilyDefaults,
+    OpenOptions, WriteSettings,
+};
+use crate::persistent::PersistentDb;
+use crate::snapshot::RocksDBSnapshot;
 use crate::transaction::RocksDBTransaction;
 use crate::write_batch::RocksDBWriteBatch;
 
@@ -31,34 +52,10 @@ use crate::write_batch::RocksDBWriteBatch;
 #[derive(Default)]
 pub struct RocksDBException;
 
-fn json_merge(
-    _new_key: &[u8],
-    existing_val: Option<&[u8]>,
-    operands: &MergeOperands,
-) -> Option<Vec<u8>> {
-    // Decode the existing value
-    let mut doc: Value = if let Some(val) = existing_val {
-        serde_json::from_slice(val).unwrap_or(Value::Array(vec![]))
-    } else {
-        Value::Array(vec![])
-    };
-
-    // Process each operand
-    for op in operands {
-        if let Ok(patch) = serde_json::from_slice::<Value>(op) {
-            let p: Patch = from_value(patch).unwrap();
-            json_patch::patch(&mut doc, &p).unwrap();
-        }
-    }
-
-    // Serialize the updated JSON object back to bytes
-    Some(serde_json::to_vec(&doc).unwrap())
-}
-
 #[derive(Debug)]
 pub struct KeyValueResult {
-    pub key: Option<String>,
-    pub value: Option<String>,
+    pub key: Option<Binary<u8>>,
+    pub value: Option<Binary<u8>>,
 }
 
 impl IntoZval for KeyValueResult {
@@ -73,169 +70,542 @@ impl IntoZval for KeyValueResult {
     }
 }
 
-fn acquire_lock(lock_file: &str) -> Result<File, PhpException> {
+pub struct ScanResult {
+    pub entries: BinaryMap<Binary<u8>>,
+    pub next: Option<Binary<u8>>,
+}
+
+impl IntoZval for ScanResult {
+    const TYPE: ext_php_rs::flags::DataType = ext_php_rs::flags::DataType::Array;
+
+    fn set_zval(self, zv: &mut Zval, _persistent: bool) -> Result<(), Error> {
+        let mut ht = ZendHashTable::new();
+        ht.insert("entries", self.entries.into_zval(false)?)?;
+        ht.insert("next", self.next.into_zval(false)?)?;
+        zv.set_hashtable(ht);
+        Ok(())
+    }
+}
+
+/// Takes the exclusive lock on `lock_file` and records our PID in it.
+///
+/// Waits indefinitely without a `timeout`; otherwise throws a
+/// `LockTimeoutException` naming the process holding the lock, if known.
+fn acquire_lock(lock_file: &str, timeout: Option<Duration>) -> Result<File, PhpException> {
     let path = Path::new(lock_file);
-    let file = File::create(&path).map_err(|e| PhpException::from(e.to_string()))?;
+    // Not truncated on open: the current holder's PID is needed for the timeout message.
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| PhpException::from(e.to_string()))?;
 
+    let started = Instant::now();
     loop {
         match file.try_lock_exclusive() {
             Ok(_) => break,
-            Err(_) => thread::sleep(Duration::from_millis(100)),
+            Err(_) => {
+                let mut wait = Duration::from_millis(100);
+                if let Some(timeout) = timeout {
+                    let elapsed = started.elapsed();
+                    if elapsed >= timeout {
+                        return Err(lock_timeout(lock_file, &mut file, timeout));
+                    }
+                    wait = wait.min(timeout - elapsed);
+                }
+                thread::sleep(wait);
+            }
         }
     }
 
+    file.set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| write!(file, "{}", process::id()))
+        .map_err(|e| PhpException::from(e.to_string()))?;
     Ok(file)
 }
 
-fn release_lock(file: File) -> PhpResult<()> {
-    file.unlock()
-        .map_err(|e| PhpException::from(e.to_string()))?;
-    Ok(())
+fn lock_timeout(lock_file: &str, file: &mut File, timeout: Duration) -> PhpException {
+    let mut contents = String::new();
+    let holder = file
+        .read_to_string(&mut contents)
+        .ok()
+        .and_then(|_| contents.trim().parse::<u32>().ok());
+    let message = match holder {
+        Some(pid) => format!(
+            "Timed out after {} ms waiting for lock file '{}' held by process {}",
+            timeout.as_millis(),
+            lock_file,
+            pid
+        ),
+        None => format!(
+            "Timed out after {} ms waiting for lock file '{}'",
+            timeout.as_millis(),
+            lock_file
+        ),
+    };
+    exception::exception(exception::LOCK_TIMEOUT, message)
+}
+
+/// Takes the lock file for `path` and opens the database, releasing the lock again on failure.
+fn open_db(path: &str, ttl_secs: Option<u64>, open_opts: &mut OpenOptions) -> PhpResult<OpenDb> {
+    let lock_file = format!("{}-php.lock", path);
+    let lock_handle = acquire_lock(&lock_file, open_opts.lock_timeout)?;
+
+    let cf_names = DB::list_cf(&open_opts.db, path).unwrap_or(vec!["default".to_string()]);
+    // On any error below, dropping `lock_handle` releases the lock.
+    let cf_options = open_opts.cf_options(cf_names)?;
+    let descriptors = cf_descriptors(&cf_options);
+    let opts = &open_opts.db;
+
+    let db = if open_opts.optimistic {
+        OptimisticTransactionDB::open_cf_descriptors(opts, path, descriptors)
+            .map(DbHandle::Optimistic)
+    } else if open_opts.transactional {
+        TransactionDB::open_cf_descriptors(opts, &open_opts.txn_db, path, descriptors)
+            .map(DbHandle::Transactional)
+    } else {
+        match ttl_secs {
+            Some(ttl) => {
+                let duration = Duration::from_secs(ttl);
+                DB::open_cf_descriptors_with_ttl(opts, path, descriptors, duration)
+            }
+            None => DB::open_cf_descriptors(opts, path, descriptors),
+        }
+        .map(DbHandle::Plain)
+    };
+
+    let db = db.map_err(|e| PhpException::from(e.to_string()))?;
+    Ok(OpenDb::new(db, cf_options, Some(lock_handle)))
 }
 
 #[php_class(name = "RocksDB")]
 pub struct RocksDB {
-    pub db: DBWithThreadMode<SingleThreaded>,
-    lock_handle: Option<File>,
-    position: Option<Vec<u8>>,
+    /// `None` once the database has been closed.
+    pub db: Option<Arc<OpenDb>>,
+    cf_defaults: ColumnFamilyDefaults,
+    write_defaults: WriteSettings,
+    /// Whether `db` is shared through the persistent registry.
+    persistent: bool,
+    cursor: Option<Cursor>,
 }
 
-#[php_impl(rename_methods = "camelCase")]
 impl RocksDB {
-    #[constructor]
-    pub fn __construct(path: String, ttl_secs: Option<u64>) -> PhpResult<Self> {
-        let lock_file = format!("{}-php.lock", path);
-        let lock_handle = acquire_lock(&lock_file)?;
-
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.set_max_open_files(1000);
-        opts.set_log_level(rust_rocksdb::LogLevel::Warn);
-        opts.set_compression_type(rust_rocksdb::DBCompressionType::Snappy);
-        opts.set_merge_operator_associative("json_merge", json_merge);
-
-        let cf_names = DB::list_cf(&opts, &path).unwrap_or(vec!["default".to_string()]);
-        let cf_descriptors: Vec<ColumnFamilyDescriptor> = cf_names
-            .iter()
-            .map(|name| {
-                let mut cf_opts = Options::default();
-                cf_opts.set_merge_operator_associative("json_merge", json_merge);
-                ColumnFamilyDescriptor::new(name, cf_opts)
-            })
-            .collect();
+    fn handle(&self) -> PhpResult<&Arc<OpenDb>> {
+        self.db.as_ref().ok_or_else(|| {
+            exception::exception(exception::CLOSED, "The database is closed".to_string())
+        })
+    }
 
-        let db = match ttl_secs {
-            Some(ttl) => {
-                let duration = Duration::from_secs(ttl);
-                DBWithThreadMode::open_cf_descriptors_with_ttl(
-                    &opts,
-                    &path,
-                    cf_descriptors,
-                    duration,
-                )
+    fn column_family(&self, cf_name: Option<&str>) -> PhpResult<Arc<BoundColumnFamily<'_>>> {
+        self.handle()?
+            .cf_handle(cf_name.unwrap_or(DEFAULT_COLUMN_FAMILY_NAME))
+            .ok_or_else(|| "Column family not found".into())
+    }
+
+    /// Write options for a single call: the instance defaults with `options` applied on top.
+    fn write_options(&self, options: Option<&ZendHashTable>) -> PhpResult<WriteOptions> {
+        Ok(self.write_defaults.with(options)?.write_options())
+    }
+
+    /// Collects up to `limit` entries between the bounds, in key order or reversed.
+    ///
+    /// Iteration starts at `token` when given, otherwise at the first (or last)
+    /// key within the bounds. The returned `next` is the first key that was not
+    /// returned, to be passed back as `token` to fetch the following page.
+    fn scan_range(
+        &self,
+        lower: Option<Vec<u8>>,
+        upper: Option<Vec<u8>>,
+        limit: Option<u64>,
+        reverse: bool,
+        cf_name: Option<&str>,
+        token: Option<&[u8]>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<ScanResult> {
+        let mut readopts = options::read_options(read_options)?;
+        if let Some(lower) = lower {
+            readopts.set_iterate_lower_bound(lower);
+        }
+        if let Some(upper) = upper {
+            readopts.set_iterate_upper_bound(upper);
+        }
+
+        let cf = self.column_family(cf_name)?;
+        let mut iter = self.handle()?.raw_iterator_cf_opt(&cf, readopts);
+        match (token, reverse) {
+            (Some(token), false) => iter.seek(token),
+            (Some(token), true) => iter.seek_for_prev(token),
+            (None, false) => iter.seek_to_first(),
+            (None, true) => iter.seek_to_last(),
+        }
+
+        let limit = limit.filter(|&limit| limit > 0).map(|limit| limit as usize);
+        let mut entries = Vec::new();
+        while iter.valid() && limit.map_or(true, |limit| entries.len() < limit) {
+            if let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                entries.push((key.to_vec(), Binary::from(value.to_vec())));
+            }
+            if reverse {
+                iter.prev();
+            } else {
+                iter.next();
             }
-            None => DBWithThreadMode::open_cf_descriptors(&opts, &path, cf_descriptors),
+        }
+        iter.status()
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+
+        let next = if iter.valid() {
+            iter.key().map(|key| Binary::from(key.to_vec()))
+        } else {
+            None
         };
+        Ok(ScanResult {
+            entries: BinaryMap(entries),
+            next,
+        })
+    }
+
+    /// Starts a fresh cursor on the default column family for the `seek*` methods.
+    fn reset_cursor(&mut self) -> PhpResult<&mut Cursor> {
+        let cursor = Cursor::new(Arc::clone(self.handle()?), None, None, None)?;
+        Ok(self.cursor.insert(cursor))
+    }
 
-        match db {
-            Ok(db) => Ok(RocksDB {
-                db,
-                lock_handle: Some(lock_handle),
-                position: None,
-            }),
-            Err(e) => {
-                let _ = release_lock(lock_handle);
-                Err(e.to_string().into())
+    /// Returns the entry under the cursor and moves it with `step`.
+    fn step_cursor(&mut self, step: fn(&mut Cursor)) -> KeyValueResult {
+        match self.cursor.as_mut() {
+            Some(cursor) if cursor.valid() => {
+                let key = cursor.key().map(|k| Binary::from(k.to_vec()));
+                let value = cursor.value().map(|v| Binary::from(v.to_vec()));
+                step(cursor);
+                KeyValueResult { key, value }
             }
+            _ => KeyValueResult {
+                key: None,
+                value: None,
+            },
         }
     }
+}
 
-    #[destructor]
-    pub fn __destruct(&self) {
-        if let Some(lock_handle) = &self.lock_handle {
-            let _ = release_lock(
-                lock_handle
-                    .try_clone()
-                    .expect("Failed to clone file handle"),
-            );
+#[php_impl(rename_methods = "camelCase")]
+impl RocksDB {
+    #[constructor]
+    pub fn __construct(
+        path: String,
+        ttl_secs: Option<u64>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<Self> {
+        let mut open_opts = db_options(options)?;
+        if open_opts.error_if_wal_file_exists {
+            return Err(PhpException::from_class::<RocksDBException>(
+                "Option 'error_if_wal_file_exists' is only supported by openReadOnly()".to_string(),
+            ));
+        }
+        if ttl_secs.is_some() && open_opts.transactional {
+            return Err(PhpException::from_class::<RocksDBException>(
+                "A TTL cannot be combined with 'transactional' => true".to_string(),
+            ));
         }
-    }
 
-    pub fn put(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .put_cf(&cf, key.as_bytes(), value.as_bytes())
-                    .map_err(|e| e.to_string().into())
+        if !open_opts.persistent {
+            // Waiting for our own lock file would never end.
+            if persistent::is_open(&path) {
+                return Err(PhpException::from_class::<RocksDBException>(format!(
+                    "Database '{}' is already open persistently in this process",
+                    path
+                )));
             }
-            None => self
-                .db
-                .put(key.as_bytes(), value.as_bytes())
-                .map_err(|e| e.to_string().into()),
+            let db = open_db(&path, ttl_secs, &mut open_opts)?;
+            return Ok(RocksDB {
+                db: Some(Arc::new(db)),
+                cf_defaults: open_opts.cf_defaults,
+                write_defaults: open_opts.write_defaults,
+                persistent: false,
+                cursor: None,
+            });
         }
+
+        let (db, cf_defaults, write_defaults) =
+            persistent::get_or_open(&path, ttl_secs, options, || {
+                let db = open_db(&path, ttl_secs, &mut open_opts)?;
+                Ok(PersistentDb::new(
+                    db,
+                    open_opts.cf_defaults.clone(),
+                    open_opts.write_defaults,
+                ))
+            })?;
+        Ok(RocksDB {
+            db: Some(db),
+            cf_defaults,
+            write_defaults,
+            persistent: true,
+            cursor: None,
+        })
     }
 
-    pub fn get(&self, key: String, cf_name: Option<String>) -> PhpResult<Option<String>> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                match self.db.get_cf(&cf, key.as_bytes()) {
-                    Ok(Some(value)) => {
-                        Ok(Some(String::from_utf8(value).map_err(|e| e.to_string())?))
-                    }
-                    Ok(None) => Ok(None),
-                    Err(e) => Err(e.to_string().into()),
-                }
+    #[destructor]
+    pub fn __destruct(&mut self) {
+        let _ = self.close();
+    }
+
+    /// Opens an existing database for reading only. No lock file is taken, so
+    /// any number of processes can do this while another one writes.
+    pub fn open_read_only(path: String, options: Option<&ZendHashTable>) -> PhpResult<RocksDB> {
+        let mut open_opts = db_options(options)?;
+        for (option, set) in [
+            ("transactional", open_opts.transactional),
+            ("persistent", open_opts.persistent),
+        ] {
+            if set {
+                return Err(PhpException::from_class::<RocksDBException>(format!(
+                    "Option '{}' is not supported by openReadOnly()",
+                    option
+                )));
             }
-            None => match self.db.get(key.as_bytes()) {
-                Ok(Some(value)) => Ok(Some(String::from_utf8(value).map_err(|e| e.to_string())?)),
-                Ok(None) => Ok(None),
-                Err(e) => Err(e.to_string().into()),
-            },
         }
+
+        let cf_names = DB::list_cf(&open_opts.db, &path).unwrap_or(vec!["default".to_string()]);
+        let cf_options = open_opts.cf_options(cf_names)?;
+        let db = DB::open_cf_descriptors_read_only(
+            &open_opts.db,
+            &path,
+            cf_descriptors(&cf_options),
+            open_opts.error_if_wal_file_exists,
+        )
+        .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+
+        Ok(RocksDB {
+            db: Some(Arc::new(OpenDb::new(
+                DbHandle::ReadOnly(db),
+                cf_options,
+                None,
+            ))),
+            cf_defaults: open_opts.cf_defaults,
+            write_defaults: open_opts.write_defaults,
+            persistent: false,
+            cursor: None,
+        })
     }
 
-    pub fn merge(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .merge_cf(&cf, key.as_bytes(), value.as_bytes())
-                    .map_err(|e| e.to_string().into())
+    /// Opens a secondary instance of the database at `primary_path`, keeping its own
+    /// info logs in `secondary_path`. Like `openReadOnly`, no lock file is taken.
+    pub fn open_as_secondary(
+        primary_path: String,
+        secondary_path: String,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<RocksDB> {
+        let mut open_opts = db_options(options)?;
+        for (option, set) in [
+            ("transactional", open_opts.transactional),
+            ("persistent", open_opts.persistent),
+            (
+                "error_if_wal_file_exists",
+                open_opts.error_if_wal_file_exists,
+            ),
+        ] {
+            if set {
+                return Err(PhpException::from_class::<RocksDBException>(format!(
+                    "Option '{}' is not supported by openAsSecondary()",
+                    option
+                )));
             }
-            None => self
-                .db
-                .merge(key.as_bytes(), value.as_bytes())
-                .map_err(|e| e.to_string().into()),
         }
+
+        // The primary may delete files at any time, so keep every file open unless told otherwise.
+        if options.map_or(true, |options| options.get("max_open_files").is_none()) {
+            open_opts.db.set_max_open_files(-1);
+        }
+
+        let cf_names =
+            DB::list_cf(&open_opts.db, &primary_path).unwrap_or(vec!["default".to_string()]);
+        let cf_options = open_opts.cf_options(cf_names)?;
+        let db = DB::open_cf_descriptors_as_secondary(
+            &open_opts.db,
+            &primary_path,
+            &secondary_path,
+            cf_descriptors(&cf_options),
+        )
+        .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+
+        Ok(RocksDB {
+            db: Some(Arc::new(OpenDb::new(
+                DbHandle::Secondary(db),
+                cf_options,
+                None,
+            ))),
+            cf_defaults: open_opts.cf_defaults,
+            write_defaults: open_opts.write_defaults,
+            persistent: false,
+            cursor: None,
+        })
     }
 
-    pub fn delete(&self, key: String, cf_name: Option<String>) -> PhpResult<()> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .delete_cf(&cf, key.as_bytes())
-                    .map_err(|e| e.to_string().into())
+    pub fn put(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+        write_options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("put")?;
+        let cf = self.column_family(cf_name.as_deref())?;
+        let write_opts = self.write_options(write_options)?;
+        self.handle()?
+            .put_cf_opt(&cf, key.as_slice(), value.as_slice(), &write_opts)
+            .map_err(exception::from_error)
+    }
+
+    pub fn get(
+        &self,
+        key: Binary<u8>,
+        cf_name: Option<String>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<Option<Binary<u8>>> {
+        let cf = self.column_family(cf_name.as_deref())?;
+        let readopts = options::read_options(read_options)?;
+        self.handle()?
+            .get_cf_opt(&cf, key.as_slice(), &readopts)
+            .map(|value| value.map(Binary::from))
+            .map_err(|e| e.to_string().into())
+    }
+
+    pub fn multi_get(
+        &self,
+        keys: Vec<Binary<u8>>,
+        cf_name: Option<String>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<BinaryMap<Option<Binary<u8>>>> {
+        let cf = self.column_family(cf_name.as_deref())?;
+        let readopts = options::read_options(read_options)?;
+        let key_slices: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        let values = self
+            .handle()?
+            .batched_multi_get_cf_opt(&cf, &key_slices, &readopts);
+
+        let mut result = Vec::with_capacity(keys.len());
+        for (key, value) in keys.into_iter().zip(values) {
+            let value =
+                value.map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            result.push((key.to_vec(), value.map(Binary::from)));
+        }
+        Ok(BinaryMap(result))
+    }
+
+    pub fn multi_get_cf(
+        &self,
+        keys_by_cf: HashMap<String, Vec<Binary<u8>>>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<HashMap<String, BinaryMap<Option<Binary<u8>>>>> {
+        let readopts = options::read_options(read_options)?;
+        let mut requests = Vec::new();
+        for (cf_name, keys) in &keys_by_cf {
+            let cf = self.column_family(Some(cf_name))?;
+            for key in keys {
+                requests.push((cf_name, cf.clone(), key));
+            }
+        }
+        let values = self.handle()?.multi_get_cf_opt(
+            requests.iter().map(|(_, cf, key)| (cf, key.as_slice())),
+            &readopts,
+        );
+
+        let mut result: HashMap<String, BinaryMap<Option<Binary<u8>>>> = keys_by_cf
+            .keys()
+            .map(|cf_name| (cf_name.clone(), BinaryMap(Vec::new())))
+            .collect();
+        for ((cf_name, _, key), value) in requests.iter().zip(values) {
+            let value =
+                value.map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            if let Some(values) = result.get_mut(*cf_name) {
+                values.0.push((key.to_vec(), value.map(Binary::from)));
             }
-            None => self
-                .db
-                .delete(key.as_bytes())
-                .map_err(|e| e.to_string().into()),
         }
+        Ok(result)
+    }
+
+    pub fn merge(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+        write_options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("merge")?;
+        let cf = self.column_family(cf_name.as_deref())?;
+        let write_opts = self.write_options(write_options)?;
+        self.handle()?
+            .merge_cf_opt(&cf, key.as_slice(), value.as_slice(), &write_opts)
+            .map_err(exception::from_error)
+    }
+
+    pub fn delete(
+        &self,
+        key: Binary<u8>,
+        cf_name: Option<String>,
+        write_options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("delete")?;
+        let cf = self.column_family(cf_name.as_deref())?;
+        let write_opts = self.write_options(write_options)?;
+        self.handle()?
+            .delete_cf_opt(&cf, key.as_slice(), &write_opts)
+            .map_err(exception::from_error)
+    }
+
+    pub fn delete_range(
+        &self,
+        from: Binary<u8>,
+        to: Binary<u8>,
+        cf_name: Option<String>,
+        write_options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("deleteRange")?;
+        let cf = self.column_family(cf_name.as_deref())?;
+        let write_opts = self.write_options(write_options)?;
+        with_common!(&self.handle()?.db, "deleteRange", |db| {
+            db.delete_range_cf_opt(&cf, from.as_slice(), to.as_slice(), &write_opts)
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+        })
+    }
+
+    pub fn delete_prefix(
+        &self,
+        prefix: Binary<u8>,
+        cf_name: Option<String>,
+        compact: Option<bool>,
+        write_options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("deletePrefix")?;
+        let cf = self.column_family(cf_name.as_deref())?;
+        let write_opts = self.write_options(write_options)?;
+        with_common!(&self.handle()?.db, "deletePrefix", |db| {
+            let end = match prefix_successor(&prefix) {
+                Some(end) => end,
+                // No key sorts after every key with this prefix, so end the range just past the last key.
+                None => {
+                    let mut iter = db.raw_iterator_cf(&cf);
+                    iter.seek_to_last();
+                    match iter.key() {
+                        Some(last) if last >= prefix.as_slice() => {
+                            let mut end = last.to_vec();
+                            end.push(0);
+                            end
+                        }
+                        _ => return Ok(()),
+                    }
+                }
+            };
+
+            db.delete_range_cf_opt(&cf, prefix.as_slice(), &end, &write_opts)
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            if compact.unwrap_or(false) {
+                db.compact_range_cf(&cf, Some(prefix.as_slice()), Some(end.as_slice()));
+            }
+            Ok(())
+        })
     }
 
     pub fn list_column_families(path: String) -> PhpResult<Vec<String>> {
@@ -246,26 +616,37 @@ impl RocksDB {
         }
     }
 
-    pub fn create_column_family(&mut self, cf_name: String) -> PhpResult<()> {
-        let cf_exists = self.db.cf_handle(&cf_name).is_some();
+    pub fn create_column_family(
+        &self,
+        cf_name: String,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<()> {
+        self.handle()?.check_writable("createColumnFamily")?;
+        let cf_exists = self.handle()?.cf_handle(&cf_name).is_some();
         if cf_exists {
             return Ok(());
         }
 
-        let mut opts = Options::default();
-        opts.set_merge_operator_associative("json_merge", json_merge);
-        self.db
-            .create_cf(&cf_name, &opts)
-            .map_err(|e| e.to_string().into())
+        let cf = self.cf_defaults.build(options)?;
+        self.handle()?
+            .create_cf(&cf_name, &cf)
+            .map_err(|e| PhpException::from(e.to_string()))?;
+        self.handle()?.set_cf_options(&cf_name, Some(cf));
+        Ok(())
     }
 
-    pub fn drop_column_family(&mut self, cf_name: String) -> PhpResult<()> {
-        let cf_exists = self.db.cf_handle(&cf_name).is_some();
+    pub fn drop_column_family(&self, cf_name: String) -> PhpResult<()> {
+        self.handle()?.check_writable("dropColumnFamily")?;
+        let cf_exists = self.handle()?.cf_handle(&cf_name).is_some();
         if !cf_exists {
             return Ok(());
         }
 
-        self.db.drop_cf(&cf_name).map_err(|e| e.to_string().into())
+        self.handle()?
+            .drop_cf(&cf_name)
+            .map_err(|e| PhpException::from(e.to_string()))?;
+        self.handle()?.set_cf_options(&cf_name, None);
+        Ok(())
     }
 
     pub fn get_property(
@@ -273,37 +654,42 @@ impl RocksDB {
         property: String,
         cf_name: Option<String>,
     ) -> PhpResult<Option<String>> {
-        match cf_name {
+        let value = match cf_name {
             Some(cf_name) => {
                 let cf = self
-                    .db
+                    .handle()?
                     .cf_handle(&cf_name)
                     .ok_or("Column family not found")?;
-                match self.db.property_value_cf(&cf, &property) {
-                    Ok(Some(value)) => Ok(Some(value)),
-                    Ok(None) => Ok(None),
-                    Err(e) => Err(e.to_string().into()),
-                }
+                with_common!(&self.handle()?.db, "getProperty", |db| {
+                    Ok(db.property_value_cf(&cf, &property))
+                })?
             }
-            None => match self.db.property_value(&property) {
-                Ok(Some(value)) => Ok(Some(value)),
-                Ok(None) => Ok(None),
-                Err(e) => Err(e.to_string().into()),
-            },
+            None => with_common!(&self.handle()?.db, "getProperty", |db| {
+                Ok(db.property_value(&property))
+            })?,
+        };
+        match value {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.to_string().into()),
         }
     }
 
     pub fn flush(&self, cf_name: Option<String>) -> PhpResult<()> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db.flush_cf(&cf).map_err(|e| e.to_string().into())
+        self.handle()?.check_writable("flush")?;
+        with_common!(&self.handle()?.db, "flush", |db| {
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    db.flush_cf(&cf)
+                }
+                None => db.flush(),
             }
-            None => self.db.flush().map_err(|e| e.to_string().into()),
-        }
+            .map_err(|e| e.to_string().into())
+        })
     }
 
     pub fn repair(path: String) -> PhpResult<()> {
@@ -311,151 +697,292 @@ impl RocksDB {
         DB::repair(&opts, path).map_err(|e| e.to_string().into())
     }
 
-    pub fn close(&self) -> PhpResult<()> {
+    /// Closes the database and releases the lock file. Closing twice is a no-op.
+    pub fn close(&mut self) -> PhpResult<()> {
+        self.cursor = None;
+        if self.persistent {
+            // The database stays open in the registry for the next request.
+            self.db = None;
+            return Ok(());
+        }
+        if let Some(db) = self.db.take() {
+            // Iterators, snapshots, batches and transactions keep the handle alive;
+            // refuse rather than report a close that did not happen.
+            if let Err(db) = Arc::try_unwrap(db) {
+                let users = Arc::strong_count(&db) - 1;
+                self.db = Some(db);
+                return Err(PhpException::from_class::<RocksDBException>(format!(
+                    "Cannot close the database while {} iterator(s), snapshot(s), write batch(es), transaction(s) or backup engine(s) still use it",
+                    users
+                )));
+            }
+        }
         Ok(())
     }
 
-    pub fn all(&self, cf_name: Option<String>) -> PhpResult<HashMap<String, String>> {
-        let mut result = HashMap::new();
-        let mut iter = match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db.iterator_cf(&cf, rust_rocksdb::IteratorMode::Start)
-            }
-            None => self.db.iterator(rust_rocksdb::IteratorMode::Start),
-        };
+    pub fn is_open(&self) -> bool {
+        self.db.is_some()
+    }
 
-        while let Some(Ok((key, value))) = iter.next() {
-            let key_str = String::from_utf8(key.to_vec()).map_err(|e| e.to_string())?;
-            let value_str = String::from_utf8(value.to_vec()).map_err(|e| e.to_string())?;
-            result.insert(key_str, value_str);
+    /// Replays whatever the primary wrote since the last catch-up.
+    pub fn try_catch_up_with_primary(&self) -> PhpResult<()> {
+        match &self.handle()?.db {
+            DbHandle::Secondary(db) => db
+                .try_catch_up_with_primary()
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string())),
+            db => Err(handle::unsupported("tryCatchUpWithPrimary", db.mode())),
         }
+    }
 
-        Ok(result)
+    pub fn snapshot(&self) -> PhpResult<RocksDBSnapshot> {
+        Ok(RocksDBSnapshot::new(Arc::clone(self.handle()?)))
+    }
+
+    pub fn create_write_batch(&self) -> PhpResult<RocksDBWriteBatch> {
+        self.handle()?.check_writable("createWriteBatch")?;
+        Ok(RocksDBWriteBatch::new(
+            Arc::clone(self.handle()?),
+            self.write_defaults,
+        ))
+    }
+
+    pub fn begin_transaction(
+        &self,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<RocksDBTransaction> {
+        RocksDBTransaction::new(
+            Arc::clone(self.handle()?),
+            self.write_defaults,
+            transaction_options(options)?,
+        )
+    }
+
+    pub fn backup_engine(
+        &self,
+        backup_path: String,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<RocksDBBackup> {
+        RocksDBBackup::new(Arc::clone(self.handle()?), &backup_path, options)
+    }
+
+    pub fn all(&self, cf_name: Option<String>) -> PhpResult<BinaryMap<Binary<u8>>> {
+        let cf = self.column_family(cf_name.as_deref())?;
+        let mut iter = self
+            .handle()?
+            .raw_iterator_cf_opt(&cf, ReadOptions::default());
+        let mut result = Vec::new();
+        iter.seek_to_first();
+        while iter.valid() {
+            if let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                result.push((key.to_vec(), Binary::from(value.to_vec())));
+            }
+            iter.next();
+        }
+        iter.status().map_err(|e| e.to_string())?;
+
+        Ok(BinaryMap(result))
     }
 
-    pub fn keys(&self, cf_name: Option<String>) -> PhpResult<Vec<String>> {
+    pub fn keys(&self, cf_name: Option<String>) -> PhpResult<Vec<Binary<u8>>> {
         let all_data = self.all(cf_name)?;
-        Ok(all_data.keys().cloned().collect())
+        Ok(all_data
+            .0
+            .into_iter()
+            .map(|(key, _)| Binary::from(key))
+            .collect())
+    }
+
+    pub fn scan(
+        &self,
+        from: Option<Binary<u8>>,
+        to: Option<Binary<u8>>,
+        limit: Option<u64>,
+        reverse: Option<bool>,
+        cf_name: Option<String>,
+        token: Option<Binary<u8>>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<ScanResult> {
+        self.scan_range(
+            from.map(|from| from.to_vec()),
+            to.map(|to| to.to_vec()),
+            limit,
+            reverse.unwrap_or(false),
+            cf_name.as_deref(),
+            token.as_ref().map(|token| token.as_slice()),
+            read_options,
+        )
+    }
+
+    pub fn scan_prefix(
+        &self,
+        prefix: Binary<u8>,
+        limit: Option<u64>,
+        reverse: Option<bool>,
+        cf_name: Option<String>,
+        token: Option<Binary<u8>>,
+        read_options: Option<&ZendHashTable>,
+    ) -> PhpResult<ScanResult> {
+        self.scan_range(
+            Some(prefix.to_vec()),
+            prefix_successor(&prefix),
+            limit,
+            reverse.unwrap_or(false),
+            cf_name.as_deref(),
+            token.as_ref().map(|token| token.as_slice()),
+            read_options,
+        )
     }
 
     // -- iterator
+    pub fn iterator(
+        &self,
+        cf_name: Option<String>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<RocksDBIterator> {
+        RocksDBIterator::new(
+            Arc::clone(self.handle()?),
+            None,
+            cf_name.as_deref(),
+            options,
+        )
+    }
+
     pub fn seek_to_first(&mut self) -> PhpResult<()> {
-        let mut iter = self.db.raw_iterator();
-        iter.seek_to_first();
-        self.position = iter.key().map(|k| k.to_vec());
+        self.reset_cursor()?.seek_to_first();
         Ok(())
     }
 
     pub fn seek_to_last(&mut self) -> PhpResult<()> {
-        let mut iter = self.db.raw_iterator();
-        iter.seek_to_last();
-        self.position = iter.key().map(|k| k.to_vec());
+        self.reset_cursor()?.seek_to_last();
         Ok(())
     }
 
-    pub fn seek(&mut self, key: String) -> PhpResult<()> {
-        let mut iter = self.db.raw_iterator();
-        iter.seek(key.as_bytes());
-        self.position = iter.key().map(|k| k.to_vec());
+    pub fn seek(&mut self, key: Binary<u8>) -> PhpResult<()> {
+        self.reset_cursor()?.seek(key.as_slice());
         Ok(())
     }
 
-    pub fn seek_for_prev(&mut self, key: String) -> PhpResult<()> {
-        let mut iter = self.db.raw_iterator();
-        iter.seek_for_prev(key.as_bytes());
-        self.position = iter.key().map(|k| k.to_vec());
+    pub fn seek_for_prev(&mut self, key: Binary<u8>) -> PhpResult<()> {
+        self.reset_cursor()?.seek_for_prev(key.as_slice());
         Ok(())
     }
 
     pub fn valid(&self) -> PhpResult<bool> {
-        let mut iter = self.db.raw_iterator();
-        if let Some(pos) = &self.position {
-            iter.seek(pos);
-        }
-        let valid = iter.valid();
-        Ok(valid)
+        self.handle()?;
+        Ok(self.cursor.as_ref().map_or(false, |cursor| cursor.valid()))
     }
 
     pub fn next(&mut self) -> PhpResult<KeyValueResult> {
-        let mut iter = self.db.raw_iterator();
-        if let Some(pos) = &self.position {
-            iter.seek(pos);
-        }
-        if iter.valid() {
-            let key = iter.key().map(|k| String::from_utf8_lossy(k).to_string());
-            let value = iter.value().map(|v| String::from_utf8_lossy(v).to_string());
-            iter.next();
-            self.position = iter.key().map(|k| k.to_vec());
-            Ok(KeyValueResult { key, value })
-        } else {
-            self.position = None;
-            Ok(KeyValueResult {
-                key: None,
-                value: None,
-            })
-        }
+        self.handle()?;
+        Ok(self.step_cursor(|cursor| cursor.next()))
     }
 
     pub fn prev(&mut self) -> PhpResult<KeyValueResult> {
-        let mut iter = self.db.raw_iterator();
-        if let Some(pos) = &self.position {
-            iter.seek(pos);
-        }
-
-        if iter.valid() {
-            let key = iter.key().map(|k| String::from_utf8_lossy(k).to_string());
-            let value = iter.value().map(|v| String::from_utf8_lossy(v).to_string());
-            iter.prev();
-            self.position = iter.key().map(|k| k.to_vec());
-            Ok(KeyValueResult { key, value })
-        } else {
-            self.position = None;
-            Ok(KeyValueResult {
-                key: None,
-                value: None,
-            })
-        }
+        self.handle()?;
+        Ok(self.step_cursor(|cursor| cursor.prev()))
     }
 
     pub fn compact_range(
         &self,
-        start: Option<String>,
-        end: Option<String>,
+        start: Option<Binary<u8>>,
+        end: Option<Binary<u8>>,
         cf_name: Option<String>,
     ) -> PhpResult<()> {
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db.compact_range_cf(
-                    &cf,
-                    start.as_ref().map(|s| s.as_bytes()),
-                    end.as_ref().map(|s| s.as_bytes()),
-                );
-            }
-            None => {
-                self.db.compact_range(
-                    start.as_ref().map(|s| s.as_bytes()),
-                    end.as_ref().map(|s| s.as_bytes()),
-                );
+        self.handle()?.check_writable("compactRange")?;
+        with_common!(&self.handle()?.db, "compactRange", |db| {
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    db.compact_range_cf(
+                        &cf,
+                        start.as_ref().map(|s| s.as_slice()),
+                        end.as_ref().map(|s| s.as_slice()),
+                    );
+                }
+                None => {
+                    db.compact_range(
+                        start.as_ref().map(|s| s.as_slice()),
+                        end.as_ref().map(|s| s.as_slice()),
+                    );
+                }
             }
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn get_live_files(&self) -> PhpResult<Vec<String>> {
-        let live_files = self
-            .db
-            .live_files()
+        with_common!(&self.handle()?.db, "getLiveFiles", |db| {
+            let live_files = db
+                .live_files()
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            let live_file_names = live_files.iter().map(|lf| lf.name.clone()).collect();
+            Ok(live_file_names)
+        })
+    }
+
+    /// Creates an openable copy of the database in `dir`, hard-linking SST files where possible.
+    ///
+    /// The memtables are always flushed first, as the bindings offer no other `log_size_for_flush`.
+    pub fn create_checkpoint(&self, dir: String) -> PhpResult<()> {
+        with_common!(&self.handle()?.db, "createCheckpoint", |db| {
+            Checkpoint::new(db)
+                .and_then(|checkpoint| checkpoint.create_checkpoint(&dir))
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+        })
+    }
+
+    /// Writes the current contents of a column family to an SST file in the new directory `dir`.
+    ///
+    /// Returns the path of the file, or `null` if the column family is empty, in
+    /// which case no directory is created. The file is written with the options
+    /// the column family was opened or created with.
+    pub fn export_column_family(&self, cf_name: String, dir: String) -> PhpResult<Option<String>> {
+        let cf = self.column_family(Some(&cf_name))?;
+        let dir = Path::new(&dir);
+        if dir.exists() {
+            return Err(PhpException::from_class::<RocksDBException>(format!(
+                "Export directory '{}' already exists",
+                dir.display()
+            )));
+        }
+        let mut readopts = ReadOptions::default();
+        readopts.fill_cache(false);
+        let mut iter = self.handle()?.raw_iterator_cf_opt(&cf, readopts);
+        iter.seek_to_first();
+        if !iter.valid() {
+            // SstFileWriter refuses to finish a file without entries.
+            iter.status()
+                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            return Ok(None);
+        }
+
+        fs::create_dir_all(dir)
             .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
-        let live_file_names = live_files.iter().map(|lf| lf.name.clone()).collect();
-        Ok(live_file_names)
+        let opts = match self.handle()?.cf_options(&cf_name) {
+            Some(opts) => opts,
+            None => self.cf_defaults.build(None)?,
+        };
+        let mut writer = SstFileWriter::create(&opts);
+        let file = dir.join("export.sst");
+        writer
+            .open(&file)
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+        while iter.valid() {
+            if let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                writer
+                    .put(key, value)
+                    .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            }
+            iter.next();
+        }
+        iter.status()
+            .and_then(|_| writer.finish())
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+
+        Ok(Some(file.to_string_lossy().into_owned()))
     }
 
     pub fn set_options(
@@ -463,23 +990,25 @@ impl RocksDB {
         options: HashMap<String, String>,
         cf_name: Option<String>,
     ) -> PhpResult<()> {
-        let options_vec: Vec<(&str, &str)> = options
-            .iter()
-            .map(|(k, v)| (k.as_str(), v.as_str()))
-            .collect();
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                let _ = self.db.set_options_cf(cf, &options_vec);
-            }
-            None => {
-                let _ = self.db.set_options(&options_vec);
+        with_common!(&self.handle()?.db, "setOptions", |db| {
+            let options_vec: Vec<(&str, &str)> = options
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    let _ = db.set_options_cf(&cf, &options_vec);
+                }
+                None => {
+                    let _ = db.set_options(&options_vec);
+                }
             }
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn set_compression(
@@ -487,72 +1016,60 @@ impl RocksDB {
         compression_type: String,
         cf_name: Option<String>,
     ) -> PhpResult<()> {
-        let compression = match compression_type.as_str() {
-            "none" => rust_rocksdb::DBCompressionType::None,
-            "snappy" => rust_rocksdb::DBCompressionType::Snappy,
-            "zlib" => rust_rocksdb::DBCompressionType::Zlib,
-            "bzip2" => rust_rocksdb::DBCompressionType::Bz2,
-            "lz4" => rust_rocksdb::DBCompressionType::Lz4,
-            "lz4hc" => rust_rocksdb::DBCompressionType::Lz4hc,
-            "zstd" => rust_rocksdb::DBCompressionType::Zstd,
-            _ => return Err("Invalid compression type".into()),
-        };
-        let mut opts = Options::default();
-        opts.set_compression_type(compression);
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .set_options_cf(cf, &[("compression", compression_type.as_str())])
+        with_common!(&self.handle()?.db, "setCompression", |db| {
+            let compression = parse_compression(&compression_type)?;
+            let mut opts = Options::default();
+            opts.set_compression_type(compression);
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    db.set_options_cf(&cf, &[("compression", compression_type.as_str())])
+                }
+                None => db.set_options(&[("compression", compression_type.as_str())]),
             }
-            None => self
-                .db
-                .set_options(&[("compression", compression_type.as_str())]),
-        }
-        .map_err(|e| e.to_string().into())
+            .map_err(|e| e.to_string().into())
+        })
     }
 
     pub fn set_write_buffer_size(&self, size: usize, cf_name: Option<String>) -> PhpResult<()> {
-        let mut opts = Options::default();
-        opts.set_write_buffer_size(size);
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .set_options_cf(cf, &[("write_buffer_size", size.to_string().as_str())])
+        with_common!(&self.handle()?.db, "setWriteBufferSize", |db| {
+            let mut opts = Options::default();
+            opts.set_write_buffer_size(size);
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    db.set_options_cf(&cf, &[("write_buffer_size", size.to_string().as_str())])
+                }
+                None => db.set_options(&[("write_buffer_size", size.to_string().as_str())]),
             }
-            None => self
-                .db
-                .set_options(&[("write_buffer_size", size.to_string().as_str())]),
-        }
-        .map_err(|e| e.to_string().into())
+            .map_err(|e| e.to_string().into())
+        })
     }
 
     pub fn set_cache_size(&self, size: usize, cf_name: Option<String>) -> PhpResult<()> {
-        let mut opts = Options::default();
-        let mut cache = rust_rocksdb::BlockBasedOptions::default();
-        cache.set_block_cache(&rust_rocksdb::Cache::new_lru_cache(size));
-        opts.set_block_based_table_factory(&cache);
-        match cf_name {
-            Some(cf_name) => {
-                let cf = self
-                    .db
-                    .cf_handle(&cf_name)
-                    .ok_or("Column family not found")?;
-                self.db
-                    .set_options_cf(cf, &[("block_cache", size.to_string().as_str())])
+        with_common!(&self.handle()?.db, "setCacheSize", |db| {
+            let mut opts = Options::default();
+            let mut cache = rust_rocksdb::BlockBasedOptions::default();
+            cache.set_block_cache(&rust_rocksdb::Cache::new_lru_cache(size));
+            opts.set_block_based_table_factory(&cache);
+            match cf_name {
+                Some(cf_name) => {
+                    let cf = self
+                        .handle()?
+                        .cf_handle(&cf_name)
+                        .ok_or("Column family not found")?;
+                    db.set_options_cf(&cf, &[("block_cache", size.to_string().as_str())])
+                }
+                None => db.set_options(&[("block_cache", size.to_string().as_str())]),
             }
-            None => self
-                .db
-                .set_options(&[("block_cache", size.to_string().as_str())]),
-        }
-        .map_err(|e| e.to_string().into())
+            .map_err(|e| e.to_string().into())
+        })
     }
 }
 
@@ -563,7 +1080,19 @@ pub extern "C" fn php_module_info(_module: *mut ModuleEntry) {
     info_table_end!();
 }
 
+pub extern "C" fn php_module_shutdown(_type: i32, _module_number: i32) -> i32 {
+    persistent::close_all();
+    0
+}
+
+#[php_startup]
+pub fn startup() {
+    exception::register().expect("Failed to register exception classes");
+}
+
 #[php_module]
 pub fn module(module: ModuleBuilder) -> ModuleBuilder {
-    module.info_function(php_module_info)
+    module
+        .info_function(php_module_info)
+        .shutdown_function(php_module_shutdown)
 }
@@ -0,0 +1,60 @@
+use crate::RocksDBException;
+use ext_php_rs::prelude::*;
+use json_patch::Patch;
+use rust_rocksdb::{MergeOperands, Options};
+use serde_json::{from_value, Value};
+
+pub fn json_merge(
+    _new_key: &[u8],
+    existing_val: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    // Decode the existing value
+    let mut doc: Value = if let Some(val) = existing_val {
+        serde_json::from_slice(val).unwrap_or(Value::Array(vec![]))
+    } else {
+        Value::Array(vec![])
+    };
+
+    // Process each operand
+    for op in operands {
+        if let Ok(patch) = serde_json::from_slice::<Value>(op) {
+            let p: Patch = from_value(patch).unwrap();
+            json_patch::patch(&mut doc, &p).unwrap();
+        }
+    }
+
+    // Serialize the updated JSON object back to bytes
+    Some(serde_json::to_vec(&doc).unwrap())
+}
+
+pub fn string_append(
+    _new_key: &[u8],
+    existing_val: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut result = existing_val.map(|val| val.to_vec()).unwrap_or_default();
+    for op in operands {
+        if !result.is_empty() {
+            result.push(b',');
+        }
+        result.extend_from_slice(op);
+    }
+    Some(result)
+}
+
+/// Installs the merge operator registered under `name` ("none" installs nothing).
+pub fn set_merge_operator(opts: &mut Options, name: &str) -> PhpResult<()> {
+    match name {
+        "json_merge" => opts.set_merge_operator_associative("json_merge", json_merge),
+        "string_append" => opts.set_merge_operator_associative("string_append", string_append),
+        "none" => {}
+        _ => {
+            return Err(PhpException::from_class::<RocksDBException>(format!(
+                "Unknown merge operator '{}'",
+                name
+            )))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,593 @@
+use crate::binary::prefix_successor;
+use crate::merge::set_merge_operator;
+use crate::RocksDBException;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendHashTable, Zval};
+use rust_rocksdb::backup::{BackupEngineOptions, RestoreOptions};
+use rust_rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBCompressionType, LogLevel,
+    OptimisticTransactionOptions, Options, ReadOptions, SliceTransform, TransactionDBOptions,
+    TransactionOptions, WriteOptions,
+};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn option_error(message: String) -> PhpException {
+    PhpException::from_class::<RocksDBException>(message)
+}
+
+fn unknown_option(key: &str) -> PhpException {
+    option_error(format!("Unknown option '{}'", key))
+}
+
+pub fn bool_option(key: &str, value: &Zval) -> PhpResult<bool> {
+    value
+        .bool()
+        .ok_or_else(|| option_error(format!("Option '{}' must be a boolean", key)))
+}
+
+pub fn int_option(key: &str, value: &Zval) -> PhpResult<i64> {
+    value
+        .long()
+        .ok_or_else(|| option_error(format!("Option '{}' must be an integer", key)))
+}
+
+pub fn i32_option(key: &str, value: &Zval) -> PhpResult<i32> {
+    i32::try_from(int_option(key, value)?)
+        .map_err(|_| option_error(format!("Option '{}' is out of range", key)))
+}
+
+pub fn uint_option(key: &str, value: &Zval) -> PhpResult<u64> {
+    let value = int_option(key, value)?;
+    if value < 0 {
+        return Err(option_error(format!(
+            "Option '{}' must not be negative",
+            key
+        )));
+    }
+    Ok(value as u64)
+}
+
+pub fn float_option(key: &str, value: &Zval) -> PhpResult<f64> {
+    value
+        .double()
+        .or_else(|| value.long().map(|v| v as f64))
+        .ok_or_else(|| option_error(format!("Option '{}' must be a number", key)))
+}
+
+pub fn string_option(key: &str, value: &Zval) -> PhpResult<String> {
+    value
+        .str()
+        .map(str::to_string)
+        .ok_or_else(|| option_error(format!("Option '{}' must be a string", key)))
+}
+
+pub fn bytes_option(key: &str, value: &Zval) -> PhpResult<Vec<u8>> {
+    value
+        .binary::<u8>()
+        .ok_or_else(|| option_error(format!("Option '{}' must be a string", key)))
+}
+
+pub fn parse_compression(name: &str) -> PhpResult<DBCompressionType> {
+    match name {
+        "none" => Ok(DBCompressionType::None),
+        "snappy" => Ok(DBCompressionType::Snappy),
+        "zlib" => Ok(DBCompressionType::Zlib),
+        "bzip2" => Ok(DBCompressionType::Bz2),
+        "lz4" => Ok(DBCompressionType::Lz4),
+        "lz4hc" => Ok(DBCompressionType::Lz4hc),
+        "zstd" => Ok(DBCompressionType::Zstd),
+        _ => Err(option_error(format!("Invalid compression type '{}'", name))),
+    }
+}
+
+fn parse_log_level(name: &str) -> PhpResult<LogLevel> {
+    match name {
+        "debug" => Ok(LogLevel::Debug),
+        "info" => Ok(LogLevel::Info),
+        "warn" => Ok(LogLevel::Warn),
+        "error" => Ok(LogLevel::Error),
+        "fatal" => Ok(LogLevel::Fatal),
+        "header" => Ok(LogLevel::Header),
+        _ => Err(option_error(format!("Invalid log level '{}'", name))),
+    }
+}
+
+/// Column family settings that can't be applied to `Options` one key at a time.
+///
+/// They are collected while parsing and applied once at the end, so the
+/// default table factory is only replaced when a table option is given and
+/// column families inherit whatever was set at the database level. The block
+/// cache is created when its size is parsed, so every column family inheriting
+/// it shares the one cache instead of getting a cache of that size each.
+#[derive(Clone, Default)]
+struct ColumnFamilySettings {
+    block_size: Option<usize>,
+    block_cache: Option<Cache>,
+    bloom_filter_bits: Option<f64>,
+    merge_operator: Option<String>,
+}
+
+impl ColumnFamilySettings {
+    fn apply(&self, opts: &mut Options) -> PhpResult<()> {
+        if self.block_size.is_some()
+            || self.block_cache.is_some()
+            || self.bloom_filter_bits.is_some()
+        {
+            let mut table = BlockBasedOptions::default();
+            if let Some(size) = self.block_size {
+                table.set_block_size(size);
+            }
+            if let Some(cache) = &self.block_cache {
+                table.set_block_cache(cache);
+            }
+            if let Some(bits) = self.bloom_filter_bits {
+                table.set_bloom_filter(bits, false);
+            }
+            opts.set_block_based_table_factory(&table);
+        }
+
+        set_merge_operator(opts, self.merge_operator.as_deref().unwrap_or("json_merge"))
+    }
+}
+
+/// Applies an option that can be tuned per column family. Returns `false` if the key is not one.
+fn apply_cf_option(
+    opts: &mut Options,
+    settings: &mut ColumnFamilySettings,
+    key: &str,
+    value: &Zval,
+) -> PhpResult<bool> {
+    match key {
+        "compression" => opts.set_compression_type(parse_compression(&string_option(key, value)?)?),
+        "bottommost_compression" => {
+            opts.set_bottommost_compression_type(parse_compression(&string_option(key, value)?)?)
+        }
+        "compression_per_level" => {
+            let levels = value.array().ok_or_else(|| {
+                option_error(format!("Option '{}' must be an array of strings", key))
+            })?;
+            let mut types = Vec::with_capacity(levels.len());
+            for (_, level) in levels.iter() {
+                types.push(parse_compression(&string_option(key, level)?)?);
+            }
+            opts.set_compression_per_level(&types);
+        }
+        "write_buffer_size" => opts.set_write_buffer_size(uint_option(key, value)? as usize),
+        "max_write_buffer_number" => opts.set_max_write_buffer_number(i32_option(key, value)?),
+        "target_file_size_base" => opts.set_target_file_size_base(uint_option(key, value)?),
+        "level_compaction_dynamic_level_bytes" => {
+            opts.set_level_compaction_dynamic_level_bytes(bool_option(key, value)?)
+        }
+        "prefix_extractor" => opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(
+            uint_option(key, value)? as usize,
+        )),
+        "block_size" => settings.block_size = Some(uint_option(key, value)? as usize),
+        "block_cache_size" => {
+            settings.block_cache = Some(Cache::new_lru_cache(uint_option(key, value)? as usize))
+        }
+        "bloom_filter_bits" => settings.bloom_filter_bits = Some(float_option(key, value)?),
+        "merge_operator" => settings.merge_operator = Some(string_option(key, value)?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The options a column family is created with when none of its own are given,
+/// i.e. everything tunable per column family that was set at the database level.
+#[derive(Clone)]
+pub struct ColumnFamilyDefaults {
+    options: Options,
+    settings: ColumnFamilySettings,
+}
+
+impl ColumnFamilyDefaults {
+    /// Builds column family options on top of the database-level defaults.
+    ///
+    /// `ttl` is rejected: RocksDB applies the TTL the database is opened with
+    /// to every column family.
+    pub fn build(&self, options: Option<&ZendHashTable>) -> PhpResult<Options> {
+        let mut opts = self.options.clone();
+        let mut settings = self.settings.clone();
+        if let Some(options) = options {
+            for (key, value) in options.iter() {
+                let key = key.to_string();
+                if key == "ttl" {
+                    return Err(option_error(
+                        "Option 'ttl' is not supported per column family, column families use the database TTL"
+                            .to_string(),
+                    ));
+                } else if !apply_cf_option(&mut opts, &mut settings, &key, value)? {
+                    return Err(unknown_option(&key));
+                }
+            }
+        }
+        settings.apply(&mut opts)?;
+
+        Ok(opts)
+    }
+}
+
+/// Write options parsed from a PHP options array.
+///
+/// Kept as plain flags rather than a `WriteOptions` so per-call options can be
+/// layered over the defaults an instance was opened with.
+#[derive(Clone, Copy, Default)]
+pub struct WriteSettings {
+    sync: bool,
+    disable_wal: bool,
+    no_slowdown: bool,
+    low_pri: bool,
+}
+
+impl WriteSettings {
+    pub fn parse(options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        Self::default().with(options)
+    }
+
+    /// Returns these settings with the given options applied on top.
+    pub fn with(mut self, options: Option<&ZendHashTable>) -> PhpResult<Self> {
+        if let Some(options) = options {
+            for (key, value) in options.iter() {
+                let key = key.to_string();
+                match key.as_str() {
+                    "sync" => self.sync = bool_option(&key, value)?,
+                    "disable_wal" => self.disable_wal = bool_option(&key, value)?,
+                    "no_slowdown" => self.no_slowdown = bool_option(&key, value)?,
+                    "low_pri" => self.low_pri = bool_option(&key, value)?,
+                    _ => return Err(unknown_option(&key)),
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn write_options(&self) -> WriteOptions {
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(self.sync);
+        write_opts.disable_wal(self.disable_wal);
+        write_opts.set_no_slowdown(self.no_slowdown);
+        write_opts.set_low_pri(self.low_pri);
+        write_opts
+    }
+}
+
+/// Everything parsed from the options array passed when opening a database.
+pub struct OpenOptions {
+    pub db: Options,
+    pub cf_defaults: ColumnFamilyDefaults,
+    pub write_defaults: WriteSettings,
+    /// Open as a `TransactionDB` so transactions can share the handle.
+    pub transactional: bool,
+    /// Open as an `OptimisticTransactionDB` instead; implies `transactional`.
+    pub optimistic: bool,
+    /// Lock manager settings of the `TransactionDB`.
+    pub txn_db: TransactionDBOptions,
+    /// How long to wait for the PHP-side lock file; `None` waits indefinitely.
+    pub lock_timeout: Option<Duration>,
+    /// Keep the database open across requests, see `persistent::get_or_open`.
+    pub persistent: bool,
+    /// Only for read-only opens: fail if there is a WAL that would have to be replayed.
+    pub error_if_wal_file_exists: bool,
+    column_families: Vec<(String, Options)>,
+}
+
+impl OpenOptions {
+    /// Returns the options of every existing column family plus the configured ones.
+    pub fn cf_options(&mut self, existing: Vec<String>) -> PhpResult<BTreeMap<String, Options>> {
+        let mut cf_options: BTreeMap<String, Options> = std::mem::take(&mut self.column_families)
+            .into_iter()
+            .collect();
+        for name in existing {
+            if !cf_options.contains_key(&name) {
+                let cf = self.cf_defaults.build(None)?;
+                cf_options.insert(name, cf);
+            }
+        }
+
+        Ok(cf_options)
+    }
+}
+
+/// Descriptors to open every column family in `cf_options` with.
+pub fn cf_descriptors(cf_options: &BTreeMap<String, Options>) -> Vec<ColumnFamilyDescriptor> {
+    cf_options
+        .iter()
+        .map(|(name, cf)| ColumnFamilyDescriptor::new(name, cf.clone()))
+        .collect()
+}
+
+/// Builds the options used to open a database from the optional PHP options array.
+///
+/// Defaults match what the extension always used: the database is created if
+/// missing, up to 1000 files are kept open, Snappy compression and Warn logging.
+/// Per column family options go under `column_families`, keyed by name.
+pub fn db_options(options: Option<&ZendHashTable>) -> PhpResult<OpenOptions> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.set_max_open_files(1000);
+    opts.set_log_level(LogLevel::Warn);
+    opts.set_compression_type(DBCompressionType::Snappy);
+
+    let mut settings = ColumnFamilySettings::default();
+    let mut column_families = None;
+    let mut write_defaults = WriteSettings::default();
+    let mut transactional = false;
+    let mut optimistic = false;
+    let mut txn_db = TransactionDBOptions::default();
+    let mut txn_db_option = None;
+    let mut lock_timeout = None;
+    let mut persistent = false;
+    let mut error_if_wal_file_exists = false;
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            match key.as_str() {
+                "create_if_missing" => opts.create_if_missing(bool_option(&key, value)?),
+                "create_missing_column_families" => {
+                    opts.create_missing_column_families(bool_option(&key, value)?)
+                }
+                "error_if_exists" => opts.set_error_if_exists(bool_option(&key, value)?),
+                "paranoid_checks" => opts.set_paranoid_checks(bool_option(&key, value)?),
+                "max_open_files" => opts.set_max_open_files(i32_option(&key, value)?),
+                "max_background_jobs" => opts.set_max_background_jobs(i32_option(&key, value)?),
+                "increase_parallelism" => opts.increase_parallelism(i32_option(&key, value)?),
+                "max_total_wal_size" => opts.set_max_total_wal_size(uint_option(&key, value)?),
+                "bytes_per_sync" => opts.set_bytes_per_sync(uint_option(&key, value)?),
+                "use_fsync" => opts.set_use_fsync(bool_option(&key, value)?),
+                "log_level" => opts.set_log_level(parse_log_level(&string_option(&key, value)?)?),
+                "transactional" => match value.str() {
+                    Some("optimistic") => (transactional, optimistic) = (true, true),
+                    Some("pessimistic") => (transactional, optimistic) = (true, false),
+                    Some(mode) => {
+                        return Err(option_error(format!(
+                            "Unknown transaction mode '{}', expected 'pessimistic' or 'optimistic'",
+                            mode
+                        )))
+                    }
+                    None => (transactional, optimistic) = (bool_option(&key, value)?, false),
+                },
+                "txn_lock_timeout" | "default_lock_timeout" | "max_num_locks" => {
+                    let value = int_option(&key, value)?;
+                    match key.as_str() {
+                        "txn_lock_timeout" => txn_db.set_txn_lock_timeout(value),
+                        "default_lock_timeout" => txn_db.set_default_lock_timeout(value),
+                        _ => txn_db.set_max_num_locks(value),
+                    }
+                    txn_db_option = Some(key);
+                }
+                "persistent" => persistent = bool_option(&key, value)?,
+                "error_if_wal_file_exists" => error_if_wal_file_exists = bool_option(&key, value)?,
+                "lock_timeout_ms" => {
+                    lock_timeout = Some(Duration::from_millis(uint_option(&key, value)?))
+                }
+                "write_options" => {
+                    let write_options = value.array().ok_or_else(|| {
+                        option_error(format!("Option '{}' must be an array", key))
+                    })?;
+                    write_defaults = WriteSettings::parse(Some(write_options))?;
+                }
+                "column_families" => {
+                    column_families = Some(value.array().ok_or_else(|| {
+                        option_error(format!("Option '{}' must be an array", key))
+                    })?)
+                }
+                _ => {
+                    if !apply_cf_option(&mut opts, &mut settings, &key, value)? {
+                        return Err(unknown_option(&key));
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(key) = txn_db_option {
+        if !transactional {
+            return Err(option_error(format!(
+                "Option '{}' requires 'transactional' => true",
+                key
+            )));
+        }
+        if optimistic {
+            return Err(option_error(format!(
+                "Option '{}' is not supported in optimistic mode",
+                key
+            )));
+        }
+    }
+
+    let cf_defaults = ColumnFamilyDefaults {
+        options: opts.clone(),
+        settings,
+    };
+    cf_defaults.settings.apply(&mut opts)?;
+
+    let mut configured = Vec::new();
+    for (name, cf_options) in column_families.iter().flat_map(|cfs| cfs.iter()) {
+        let cf_options = cf_options.array().ok_or_else(|| {
+            option_error(format!(
+                "Options for column family '{}' must be an array",
+                name
+            ))
+        })?;
+        configured.push((name.to_string(), cf_defaults.build(Some(cf_options))?));
+    }
+
+    Ok(OpenOptions {
+        db: opts,
+        cf_defaults,
+        write_defaults,
+        transactional,
+        optimistic,
+        txn_db,
+        lock_timeout,
+        persistent,
+        error_if_wal_file_exists,
+        column_families: configured,
+    })
+}
+
+/// Applies an option shared by point lookups, scans and iterators. Returns `false` if the key is not one.
+fn apply_read_option(readopts: &mut ReadOptions, key: &str, value: &Zval) -> PhpResult<bool> {
+    match key {
+        "fill_cache" => readopts.fill_cache(bool_option(key, value)?),
+        "verify_checksums" => readopts.set_verify_checksums(bool_option(key, value)?),
+        "readahead_size" => readopts.set_readahead_size(uint_option(key, value)? as usize),
+        "tailing" => readopts.set_tailing(bool_option(key, value)?),
+        "prefix_same_as_start" => readopts.set_prefix_same_as_start(bool_option(key, value)?),
+        "total_order_seek" => readopts.set_total_order_seek(bool_option(key, value)?),
+        // RocksDB wants an absolute time in microseconds since the epoch; PHP passes a budget in ms.
+        "deadline" => {
+            let since_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let budget = Duration::from_millis(uint_option(key, value)?);
+            readopts.set_deadline((since_epoch + budget).as_micros() as u64)
+        }
+        "io_timeout" => readopts.set_io_timeout(uint_option(key, value)? * 1000),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Builds the read options for a point lookup or scan from the optional PHP options array.
+pub fn read_options(options: Option<&ZendHashTable>) -> PhpResult<ReadOptions> {
+    let mut readopts = ReadOptions::default();
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            if !apply_read_option(&mut readopts, &key, value)? {
+                return Err(unknown_option(&key));
+            }
+        }
+    }
+    Ok(readopts)
+}
+
+/// Builds the read options for an iterator from the optional PHP options array.
+///
+/// On top of the common read options, `lower_bound` is inclusive and
+/// `upper_bound` exclusive. `prefix` restricts the iterator to keys starting
+/// with it by setting both bounds.
+pub fn iterator_options(options: Option<&ZendHashTable>) -> PhpResult<ReadOptions> {
+    let mut readopts = ReadOptions::default();
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            match key.as_str() {
+                "lower_bound" => readopts.set_iterate_lower_bound(bytes_option(&key, value)?),
+                "upper_bound" => readopts.set_iterate_upper_bound(bytes_option(&key, value)?),
+                "prefix" => {
+                    let prefix = bytes_option(&key, value)?;
+                    if let Some(end) = prefix_successor(&prefix) {
+                        readopts.set_iterate_upper_bound(end);
+                    }
+                    readopts.set_iterate_lower_bound(prefix);
+                }
+                _ => {
+                    if !apply_read_option(&mut readopts, &key, value)? {
+                        return Err(unknown_option(&key));
+                    }
+                }
+            }
+        }
+    }
+    Ok(readopts)
+}
+
+/// Options for a single transaction, parsed from a PHP options array.
+pub struct TransactionSettings {
+    pub pessimistic: TransactionOptions,
+    pub optimistic: OptimisticTransactionOptions,
+    /// The first option given that only applies to pessimistic transactions.
+    pub lock_option: Option<String>,
+    /// Commit instead of rolling back when the transaction is destroyed while still active.
+    pub commit_on_destruct: bool,
+}
+
+/// Builds the options for a single transaction from the optional PHP options array.
+///
+/// `lock_timeout` and `expiration` are in milliseconds; a negative lock timeout waits indefinitely.
+pub fn transaction_options(options: Option<&ZendHashTable>) -> PhpResult<TransactionSettings> {
+    let mut settings = TransactionSettings {
+        pessimistic: TransactionOptions::default(),
+        optimistic: OptimisticTransactionOptions::default(),
+        lock_option: None,
+        commit_on_destruct: false,
+    };
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            let txn_opts = &mut settings.pessimistic;
+            match key.as_str() {
+                "commit_on_destruct" => {
+                    settings.commit_on_destruct = bool_option(&key, value)?;
+                    continue;
+                }
+                "set_snapshot" => {
+                    let set_snapshot = bool_option(&key, value)?;
+                    txn_opts.set_snapshot(set_snapshot);
+                    settings.optimistic.set_snapshot(set_snapshot);
+                    continue;
+                }
+                "lock_timeout" => txn_opts.set_lock_timeout(int_option(&key, value)?),
+                "deadlock_detect" => txn_opts.set_deadlock_detect(bool_option(&key, value)?),
+                "expiration" => txn_opts.set_expiration(int_option(&key, value)?),
+                _ => return Err(unknown_option(&key)),
+            }
+            settings.lock_option.get_or_insert(key);
+        }
+    }
+    Ok(settings)
+}
+
+/// Restore options parsed from a PHP options array.
+pub struct RestoreSettings {
+    pub options: RestoreOptions,
+    /// Where to restore the WAL files, `None` for the database directory.
+    pub wal_dir: Option<String>,
+}
+
+/// Builds the options for restoring a backup from the optional PHP options array.
+pub fn restore_options(options: Option<&ZendHashTable>) -> PhpResult<RestoreSettings> {
+    let mut settings = RestoreSettings {
+        options: RestoreOptions::default(),
+        wal_dir: None,
+    };
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            match key.as_str() {
+                "wal_dir" => settings.wal_dir = Some(string_option(&key, value)?),
+                "keep_log_files" => settings
+                    .options
+                    .set_keep_log_files(bool_option(&key, value)?),
+                _ => return Err(unknown_option(&key)),
+            }
+        }
+    }
+    Ok(settings)
+}
+
+/// Builds the options for a backup engine at `backup_path` from the optional PHP options array.
+///
+/// The bindings only expose `max_background_operations`, so that is the only option.
+pub fn backup_engine_options(
+    backup_path: &str,
+    options: Option<&ZendHashTable>,
+) -> PhpResult<BackupEngineOptions> {
+    let mut opts =
+        BackupEngineOptions::new(backup_path).map_err(|e| option_error(e.to_string()))?;
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            let key = key.to_string();
+            match key.as_str() {
+                "max_background_operations" => {
+                    opts.set_max_background_operations(i32_option(&key, value)?)
+                }
+                _ => return Err(unknown_option(&key)),
+            }
+        }
+    }
+    Ok(opts)
+}
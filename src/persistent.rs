@@ -0,0 +1,213 @@
+use crate::handle::OpenDb;
+use crate::options::{ColumnFamilyDefaults, WriteSettings};
+use crate::RocksDBException;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendHashTable, Zval};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A database kept open for the lifetime of the worker process.
+pub struct PersistentDb {
+    pub db: Arc<OpenDb>,
+    pub cf_defaults: ColumnFamilyDefaults,
+    pub write_defaults: WriteSettings,
+}
+
+impl PersistentDb {
+    pub fn new(
+        db: OpenDb,
+        cf_defaults: ColumnFamilyDefaults,
+        write_defaults: WriteSettings,
+    ) -> Self {
+        PersistentDb {
+            db: Arc::new(db),
+            cf_defaults,
+            write_defaults,
+        }
+    }
+}
+
+/// A persistent database with the TTL and options it was opened with.
+struct Registered {
+    ttl_secs: Option<u64>,
+    options: Canonical,
+    persistent: PersistentDb,
+}
+
+/// A path in the registry: either being opened by some thread, or open.
+enum Slot {
+    Opening,
+    Open(Registered),
+}
+
+/// Persistent databases keyed by canonical path. This process holds the lock
+/// file of each, so a path is never opened a second time.
+static REGISTRY: Mutex<BTreeMap<PathBuf, Slot>> = Mutex::new(BTreeMap::new());
+
+/// Signalled whenever a path leaves `Slot::Opening`.
+static OPENED: Condvar = Condvar::new();
+
+/// Resolves `path` the same way however it is spelled. A database that does not
+/// exist yet is resolved through its parent directory.
+fn canonical_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            fs::canonicalize(parent)
+                .map(|parent| parent.join(name))
+                .unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// An options array in a form that compares equal however it was written:
+/// keys are sorted at every level and scalars normalised, so `1`, `1.0`,
+/// `true` and `"1"` all match.
+#[derive(PartialEq)]
+enum Canonical {
+    Null,
+    Scalar(String),
+    Array(BTreeMap<String, Canonical>),
+}
+
+impl Canonical {
+    fn of_array(array: &ZendHashTable) -> Self {
+        Canonical::Array(
+            array
+                .iter()
+                .map(|(key, value)| (key.to_string(), Canonical::of(value)))
+                .collect(),
+        )
+    }
+
+    fn of(value: &Zval) -> Self {
+        if let Some(array) = value.array() {
+            return Canonical::of_array(array);
+        }
+        if value.is_null() {
+            return Canonical::Null;
+        }
+        let scalar = if let Some(value) = value.bool() {
+            i64::from(value).to_string()
+        } else if let Some(value) = value.long() {
+            value.to_string()
+        } else if let Some(value) = value.double() {
+            number(value)
+        } else if let Some(value) = value.str() {
+            match value.trim().parse::<f64>() {
+                Ok(parsed) => number(parsed),
+                Err(_) => value.to_string(),
+            }
+        } else {
+            format!("{:?}", value)
+        };
+        Canonical::Scalar(scalar)
+    }
+}
+
+/// Formats whole numbers without a fraction, so `1.0` and `1` compare equal.
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        (value as i64).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// The open options to compare later opens against. `lock_timeout_ms` is left
+/// out, as reusing the handle never waits for the lock.
+fn fingerprint(options: Option<&ZendHashTable>) -> Canonical {
+    let mut entries = match options.map(Canonical::of_array) {
+        Some(Canonical::Array(entries)) => entries,
+        _ => BTreeMap::new(),
+    };
+    entries.remove("lock_timeout_ms");
+    Canonical::Array(entries)
+}
+
+/// Whether this process already keeps the database at `path` open persistently,
+/// or is opening it.
+pub fn is_open(path: &str) -> bool {
+    REGISTRY.lock().unwrap().contains_key(&canonical_path(path))
+}
+
+/// Returns the database registered for `path`, opening and registering it with `open` first if needed.
+///
+/// Throws a `RocksDBException` if it is registered with a different TTL or options.
+pub fn get_or_open(
+    path: &str,
+    ttl_secs: Option<u64>,
+    options: Option<&ZendHashTable>,
+    open: impl FnOnce() -> PhpResult<PersistentDb>,
+) -> PhpResult<(Arc<OpenDb>, ColumnFamilyDefaults, WriteSettings)> {
+    let options = fingerprint(options);
+    let key = canonical_path(path);
+    let mut registry = REGISTRY.lock().unwrap();
+    loop {
+        match registry.get(&key) {
+            // Only callers for this path wait while it is opened, possibly for the lock file.
+            Some(Slot::Opening) => registry = OPENED.wait(registry).unwrap(),
+            Some(Slot::Open(registered)) => {
+                if registered.ttl_secs != ttl_secs || registered.options != options {
+                    return Err(PhpException::from_class::<RocksDBException>(format!(
+                        "Database '{}' is already open persistently with a different TTL or options",
+                        path
+                    )));
+                }
+                return Ok(handles(&registered.persistent));
+            }
+            None => break,
+        }
+    }
+    registry.insert(key.clone(), Slot::Opening);
+    drop(registry);
+
+    let opened = open();
+    let mut registry = REGISTRY.lock().unwrap();
+    let result = match opened {
+        Ok(persistent) => {
+            let result = handles(&persistent);
+            registry.insert(
+                key,
+                Slot::Open(Registered {
+                    ttl_secs,
+                    options,
+                    persistent,
+                }),
+            );
+            Ok(result)
+        }
+        Err(e) => {
+            registry.remove(&key);
+            Err(e)
+        }
+    };
+    OPENED.notify_all();
+    result
+}
+
+fn handles(persistent: &PersistentDb) -> (Arc<OpenDb>, ColumnFamilyDefaults, WriteSettings) {
+    (
+        Arc::clone(&persistent.db),
+        persistent.cf_defaults.clone(),
+        persistent.write_defaults,
+    )
+}
+
+/// Drops every persistent database. Each is closed, and its lock file released,
+/// once no iterator, snapshot or other object still uses it.
+pub fn close_all() {
+    let registry = std::mem::take(&mut *REGISTRY.lock().unwrap());
+    drop(registry);
+}
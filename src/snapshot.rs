@@ -0,0 +1,149 @@
+use crate::binary::BinaryMap;
+use crate::handle::{DbHandle, OpenDb, SnapshotRef};
+use crate::iterator::RocksDBIterator;
+use crate::RocksDBException;
+use ext_php_rs::binary::Binary;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::ZendHashTable;
+use rust_rocksdb::{BoundColumnFamily, ReadOptions, DEFAULT_COLUMN_FAMILY_NAME};
+use self_cell::self_cell;
+use std::sync::Arc;
+
+/// A snapshot of the handle's database, `'db` being the handle's lifetime.
+pub type SnapshotBox<'db> = Box<dyn SnapshotRef + 'db>;
+
+self_cell!(
+    /// A snapshot together with the handle it was taken from.
+    ///
+    /// Shared between a `RocksDBSnapshot` and the iterators created from it, so the
+    /// snapshot stays valid for as long as any of them is alive. The snapshot is
+    /// always released before the handle.
+    pub struct SharedSnapshot {
+        owner: Arc<OpenDb>,
+
+        #[covariant]
+        dependent: SnapshotBox,
+    }
+);
+
+impl SharedSnapshot {
+    /// Makes reads through `readopts` see the database as of this snapshot.
+    pub fn apply(&self, readopts: &mut ReadOptions) {
+        self.borrow_dependent().apply(readopts);
+    }
+
+    fn db(&self) -> &DbHandle {
+        &self.borrow_owner().db
+    }
+
+    fn read_options(&self) -> ReadOptions {
+        let mut readopts = ReadOptions::default();
+        self.apply(&mut readopts);
+        readopts
+    }
+
+    fn column_family(&self, cf_name: Option<&str>) -> PhpResult<Arc<BoundColumnFamily<'_>>> {
+        self.db()
+            .cf_handle(cf_name.unwrap_or(DEFAULT_COLUMN_FAMILY_NAME))
+            .ok_or_else(|| "Column family not found".into())
+    }
+}
+
+#[php_class]
+pub struct RocksDBSnapshot {
+    snapshot: Option<Arc<SharedSnapshot>>,
+}
+
+impl RocksDBSnapshot {
+    pub fn new(db: Arc<OpenDb>) -> Self {
+        RocksDBSnapshot {
+            snapshot: Some(Arc::new(SharedSnapshot::new(db, |db| db.snapshot()))),
+        }
+    }
+
+    fn active(&self) -> PhpResult<&Arc<SharedSnapshot>> {
+        self.snapshot.as_ref().ok_or_else(|| {
+            PhpException::from_class::<RocksDBException>("Snapshot has been released".to_string())
+        })
+    }
+}
+
+#[php_impl]
+impl RocksDBSnapshot {
+    pub fn get(&self, key: Binary<u8>, cf_name: Option<String>) -> PhpResult<Option<Binary<u8>>> {
+        let shared = self.active()?;
+        let cf = shared.column_family(cf_name.as_deref())?;
+        shared
+            .db()
+            .get_cf_opt(&cf, key.as_slice(), &shared.read_options())
+            .map(|value| value.map(Binary::from))
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))
+    }
+
+    pub fn multi_get(
+        &self,
+        keys: Vec<Binary<u8>>,
+        cf_name: Option<String>,
+    ) -> PhpResult<BinaryMap<Option<Binary<u8>>>> {
+        let shared = self.active()?;
+        let cf = shared.column_family(cf_name.as_deref())?;
+        let values = shared.db().multi_get_cf_opt(
+            keys.iter().map(|key| (&cf, key.as_slice())),
+            &shared.read_options(),
+        );
+
+        let mut result = Vec::with_capacity(keys.len());
+        for (key, value) in keys.into_iter().zip(values) {
+            let value =
+                value.map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            result.push((key.to_vec(), value.map(Binary::from)));
+        }
+        Ok(BinaryMap(result))
+    }
+
+    pub fn all(&self, cf_name: Option<String>) -> PhpResult<BinaryMap<Binary<u8>>> {
+        let shared = self.active()?;
+        let cf = shared.column_family(cf_name.as_deref())?;
+        let mut iter = shared.db().raw_iterator_cf_opt(&cf, shared.read_options());
+        let mut result = Vec::new();
+        iter.seek_to_first();
+        while iter.valid() {
+            if let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+                result.push((key.to_vec(), Binary::from(value.to_vec())));
+            }
+            iter.next();
+        }
+        iter.status()
+            .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+
+        Ok(BinaryMap(result))
+    }
+
+    pub fn keys(&self, cf_name: Option<String>) -> PhpResult<Vec<Binary<u8>>> {
+        let all_data = self.all(cf_name)?;
+        Ok(all_data
+            .0
+            .into_iter()
+            .map(|(key, _)| Binary::from(key))
+            .collect())
+    }
+
+    pub fn iterator(
+        &self,
+        cf_name: Option<String>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<RocksDBIterator> {
+        let shared = self.active()?;
+        RocksDBIterator::new(
+            Arc::clone(shared.borrow_owner()),
+            Some(Arc::clone(shared)),
+            cf_name.as_deref(),
+            options,
+        )
+    }
+
+    pub fn release(&mut self) -> PhpResult<()> {
+        self.snapshot = None;
+        Ok(())
+    }
+}
@@ -1,224 +1,257 @@
+use crate::binary::BinaryMap;
+use crate::exception;
+use crate::handle::{DbHandle, OpenDb, Txn};
+use crate::options::{transaction_options, TransactionSettings, WriteSettings};
 use crate::RocksDBException;
+use ext_php_rs::binary::Binary;
 use ext_php_rs::prelude::*;
+use ext_php_rs::types::ZendHashTable;
 use rust_rocksdb::{
-    Options, Transaction, TransactionDB, TransactionDBOptions, TransactionOptions, WriteOptions,
+    BoundColumnFamily, Options, TransactionDB, TransactionDBOptions, DEFAULT_COLUMN_FAMILY_NAME,
 };
+use self_cell::self_cell;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
+/// The current transaction, `None` once it has been committed or rolled back.
+type MaybeTxn<'db> = Option<Txn<'db>>;
+
+self_cell!(
+    /// A transaction owned together with the handle it borrows.
+    ///
+    /// The transaction is always dropped before the handle, whichever of
+    /// `RocksDB` and `RocksDBTransaction` PHP releases first.
+    struct OwnedTxn {
+        owner: Arc<OpenDb>,
+
+        #[covariant]
+        dependent: MaybeTxn,
+    }
+);
+
 #[php_class]
 pub struct RocksDBTransaction {
-    transaction_db: Arc<TransactionDB>,
-    transaction: Arc<Mutex<Option<Transaction<'static, TransactionDB>>>>,
+    transaction: Mutex<OwnedTxn>,
+    write_settings: WriteSettings,
+    txn_settings: TransactionSettings,
 }
 
-fn create_transaction(transaction_db: &Arc<TransactionDB>) -> Transaction<'static, TransactionDB> {
-    let txn_opts = TransactionOptions::default();
-    let write_opts = WriteOptions::default();
-    unsafe {
-        std::mem::transmute::<Transaction<TransactionDB>, Transaction<'static, TransactionDB>>(
-            transaction_db.transaction_opt(&write_opts, &txn_opts),
-        )
+fn no_active_transaction() -> PhpException {
+    PhpException::from_class::<RocksDBException>("No active transaction".to_string())
+}
+
+fn column_family<'a>(
+    db: &'a DbHandle,
+    cf_name: Option<&str>,
+) -> PhpResult<Arc<BoundColumnFamily<'a>>> {
+    db.cf_handle(cf_name.unwrap_or(DEFAULT_COLUMN_FAMILY_NAME))
+        .ok_or_else(|| "Column family not found".into())
+}
+
+impl RocksDBTransaction {
+    /// Begins a transaction on an already open transactional database.
+    pub fn new(
+        db: Arc<OpenDb>,
+        write_settings: WriteSettings,
+        txn_settings: TransactionSettings,
+    ) -> PhpResult<Self> {
+        let write_opts = write_settings.write_options();
+        let transaction = OwnedTxn::try_new(db, |db| {
+            db.transaction(&write_opts, &txn_settings).map(Some)
+        })?;
+        Ok(RocksDBTransaction {
+            transaction: Mutex::new(transaction),
+            write_settings,
+            txn_settings,
+        })
+    }
+
+    /// Runs `f` with the active transaction and the handle it belongs to.
+    fn with_active<R>(
+        &self,
+        f: impl for<'a> FnOnce(&'a DbHandle, &Txn<'a>) -> PhpResult<R>,
+    ) -> PhpResult<R> {
+        let cell = self.transaction.lock().unwrap();
+        cell.with_dependent(|db, txn| f(&db.db, txn.as_ref().ok_or_else(no_active_transaction)?))
+    }
+
+    /// Ends the active transaction, if any, and returns what `f` made of it.
+    fn end<R>(&self, f: impl for<'a> FnOnce(Option<Txn<'a>>) -> R) -> R {
+        let mut cell = self.transaction.lock().unwrap();
+        cell.with_dependent_mut(|_, txn| f(txn.take()))
     }
 }
 
 #[php_impl]
 impl RocksDBTransaction {
     #[constructor]
-    pub fn __construct(path: String) -> PhpResult<Self> {
+    pub fn __construct(
+        path: String,
+        write_options: Option<&ZendHashTable>,
+        options: Option<&ZendHashTable>,
+    ) -> PhpResult<Self> {
+        let write_settings = WriteSettings::parse(write_options)?;
+        let txn_settings = transaction_options(options)?;
         let txn_db_opts = TransactionDBOptions::default();
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.set_max_open_files(1000);
         opts.set_log_level(rust_rocksdb::LogLevel::Warn);
 
-        let transaction_db = TransactionDB::open(&opts, &txn_db_opts, &path).map_err(|e| {
-            ext_php_rs::exception::PhpException::from_class::<RocksDBException>(e.to_string())
-        })?;
-
-        let transaction_db = Arc::new(transaction_db);
-        let transaction = create_transaction(&transaction_db);
+        let transaction_db =
+            TransactionDB::open(&opts, &txn_db_opts, &path).map_err(exception::from_error)?;
 
-        Ok(RocksDBTransaction {
-            transaction_db: Arc::clone(&transaction_db),
-            transaction: Arc::new(Mutex::new(Some(unsafe {
-                std::mem::transmute::<Transaction<TransactionDB>, Transaction<'static, TransactionDB>>(
-                    transaction,
-                )
-            }))),
-        })
+        Self::new(
+            Arc::new(OpenDb::new(
+                DbHandle::Transactional(transaction_db),
+                BTreeMap::new(),
+                None,
+            )),
+            write_settings,
+            txn_settings,
+        )
     }
 
+    /// Rolls back a transaction that is still active, or commits it with `commit_on_destruct`.
     #[destructor]
     pub fn __destruct(&self) {
-        let mut txn_guard = self.transaction.lock().unwrap();
-        if let Some(txn) = txn_guard.take() {
-            let _ = txn.commit(); // Ignoring any errors on destruction
-        }
+        let commit_on_destruct = self.txn_settings.commit_on_destruct;
+        self.end(|txn| {
+            if let Some(txn) = txn {
+                // Errors can't be reported from a destructor.
+                if commit_on_destruct {
+                    let _ = txn.commit();
+                } else {
+                    let _ = txn.rollback();
+                }
+            }
+        });
+    }
+
+    /// Starts a new transaction after the previous one was committed or rolled back.
+    pub fn begin(&self) -> PhpResult<()> {
+        let write_opts = self.write_settings.write_options();
+        let mut cell = self.transaction.lock().unwrap();
+        cell.with_dependent_mut(|db, txn| {
+            if txn.is_some() {
+                return Err(PhpException::from_class::<RocksDBException>(
+                    "Transaction is already active".to_string(),
+                ));
+            }
+            *txn = Some(db.transaction(&write_opts, &self.txn_settings)?);
+            Ok(())
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.transaction
+            .lock()
+            .unwrap()
+            .borrow_dependent()
+            .is_some()
     }
 
+    /// Commits and ends the transaction. It stays ended even if the commit fails.
     pub fn commit(&self) -> PhpResult<()> {
-        let mut txn_guard = self.transaction.lock().unwrap();
-        if let Some(txn) = txn_guard.take() {
-            txn.commit().map_err(|e| {
-                ext_php_rs::exception::PhpException::from_class::<RocksDBException>(e.to_string())
-            })?;
-        }
-        *txn_guard = Some(create_transaction(&self.transaction_db));
-        Ok(())
+        self.end(|txn| txn.ok_or_else(no_active_transaction)?.commit())
     }
 
+    /// Rolls back and ends the transaction. Does nothing if it has already ended.
     pub fn rollback(&self) -> PhpResult<()> {
-        let mut txn_guard = self.transaction.lock().unwrap();
-        if let Some(txn) = txn_guard.take() {
-            txn.rollback().map_err(|e| {
-                ext_php_rs::exception::PhpException::from_class::<RocksDBException>(e.to_string())
-            })?;
-        }
-        *txn_guard = Some(create_transaction(&self.transaction_db));
-        Ok(())
+        self.end(|txn| match txn {
+            Some(txn) => txn.rollback().map_err(exception::from_error),
+            None => Ok(()),
+        })
     }
 
     pub fn set_savepoint(&self) -> PhpResult<()> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
+        self.with_active(|_, txn| {
             txn.set_savepoint();
-        }
-        Ok(())
+            Ok(())
+        })
     }
 
     pub fn rollback_to_savepoint(&self) -> PhpResult<()> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
-            txn.rollback_to_savepoint().map_err(|e| {
-                ext_php_rs::exception::PhpException::from_class::<RocksDBException>(e.to_string())
-            })?;
-        }
-        Ok(())
-    }
-
-    pub fn put(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .transaction_db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    txn.put_cf(&cf, key.as_bytes(), value.as_bytes())
-                        .map_err(|e| {
-                            ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                                e.to_string(),
-                            )
-                        })
-                }
-                None => txn.put(key.as_bytes(), value.as_bytes()).map_err(|e| {
-                    ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                        e.to_string(),
-                    )
-                }),
-            }
-        } else {
-            Err(ext_php_rs::exception::PhpException::from_class::<
-                RocksDBException,
-            >("No active transaction".to_string()))
-        }
-    }
-
-    pub fn get(&self, key: String, cf_name: Option<String>) -> PhpResult<Option<String>> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .transaction_db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    match txn.get_cf(&cf, key.as_bytes()) {
-                        Ok(Some(value)) => Ok(Some(String::from_utf8(value).map_err(|e| {
-                            ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                                e.to_string(),
-                            )
-                        })?)),
-                        Ok(None) => Ok(None),
-                        Err(e) => Err(ext_php_rs::exception::PhpException::from_class::<
-                            RocksDBException,
-                        >(e.to_string())),
-                    }
-                }
-                None => match txn.get(key.as_bytes()) {
-                    Ok(Some(value)) => Ok(Some(String::from_utf8(value).map_err(|e| {
-                        ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                            e.to_string(),
-                        )
-                    })?)),
-                    Ok(None) => Ok(None),
-                    Err(e) => Err(ext_php_rs::exception::PhpException::from_class::<
-                        RocksDBException,
-                    >(e.to_string())),
-                },
-            }
-        } else {
-            Err(ext_php_rs::exception::PhpException::from_class::<
-                RocksDBException,
-            >("No active transaction".to_string()))
-        }
-    }
-
-    pub fn delete(&self, key: String, cf_name: Option<String>) -> PhpResult<()> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .transaction_db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    txn.delete_cf(&cf, key.as_bytes()).map_err(|e| {
-                        ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                            e.to_string(),
-                        )
-                    })
-                }
-                None => txn.delete(key.as_bytes()).map_err(|e| {
-                    ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                        e.to_string(),
-                    )
-                }),
-            }
-        } else {
-            Err(ext_php_rs::exception::PhpException::from_class::<
-                RocksDBException,
-            >("No active transaction".to_string()))
-        }
-    }
-
-    pub fn merge(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
-        let txn_guard = self.transaction.lock().unwrap();
-        if let Some(ref txn) = *txn_guard {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .transaction_db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    txn.merge_cf(&cf, key.as_bytes(), value.as_bytes())
-                        .map_err(|e| {
-                            ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                                e.to_string(),
-                            )
-                        })
-                }
-                None => txn.merge(key.as_bytes(), value.as_bytes()).map_err(|e| {
-                    ext_php_rs::exception::PhpException::from_class::<RocksDBException>(
-                        e.to_string(),
-                    )
-                }),
+        self.with_active(|_, txn| txn.rollback_to_savepoint().map_err(exception::from_error))
+    }
+
+    pub fn put(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+    ) -> PhpResult<()> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            txn.put_cf(&cf, key.as_slice(), value.as_slice())
+                .map_err(exception::from_error)
+        })
+    }
+
+    pub fn get(&self, key: Binary<u8>, cf_name: Option<String>) -> PhpResult<Option<Binary<u8>>> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            txn.get_cf(&cf, key.as_slice())
+                .map(|value| value.map(Binary::from))
+                .map_err(exception::from_error)
+        })
+    }
+
+    /// Reads a key and locks it until the transaction ends, so no one else can write it meanwhile.
+    pub fn get_for_update(
+        &self,
+        key: Binary<u8>,
+        exclusive: Option<bool>,
+        cf_name: Option<String>,
+    ) -> PhpResult<Option<Binary<u8>>> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            txn.get_for_update_cf(&cf, key.as_slice(), exclusive.unwrap_or(true))
+                .map(|value| value.map(Binary::from))
+                .map_err(exception::from_error)
+        })
+    }
+
+    /// Reads and locks several keys, in the order given.
+    pub fn multi_get_for_update(
+        &self,
+        keys: Vec<Binary<u8>>,
+        exclusive: Option<bool>,
+        cf_name: Option<String>,
+    ) -> PhpResult<BinaryMap<Option<Binary<u8>>>> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            let exclusive = exclusive.unwrap_or(true);
+
+            let mut result = Vec::with_capacity(keys.len());
+            for key in keys {
+                let value = txn
+                    .get_for_update_cf(&cf, key.as_slice(), exclusive)
+                    .map_err(exception::from_error)?;
+                result.push((key.to_vec(), value.map(Binary::from)));
             }
-        } else {
-            Err(ext_php_rs::exception::PhpException::from_class::<
-                RocksDBException,
-            >("No active transaction".to_string()))
-        }
+            Ok(BinaryMap(result))
+        })
+    }
+
+    pub fn delete(&self, key: Binary<u8>, cf_name: Option<String>) -> PhpResult<()> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            txn.delete_cf(&cf, key.as_slice())
+                .map_err(exception::from_error)
+        })
+    }
+
+    pub fn merge(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+    ) -> PhpResult<()> {
+        self.with_active(|db, txn| {
+            let cf = column_family(db, cf_name.as_deref())?;
+            txn.merge_cf(&cf, key.as_slice(), value.as_slice())
+                .map_err(exception::from_error)
+        })
     }
 }
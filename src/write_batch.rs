@@ -1,13 +1,37 @@
-use crate::RocksDBException;
+use crate::handle::{Batch, DbHandle, OpenDb};
+use crate::options::WriteSettings;
+use ext_php_rs::binary::Binary;
 use ext_php_rs::prelude::*;
-use rust_rocksdb::{Options, WriteBatchWithTransaction, DB};
+use ext_php_rs::types::ZendHashTable;
+use rust_rocksdb::{BoundColumnFamily, Options, DB, DEFAULT_COLUMN_FAMILY_NAME};
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[php_class]
 pub struct RocksDBWriteBatch {
-    db: Arc<DB>,
-    write_batch: Mutex<Option<WriteBatchWithTransaction<false>>>,
+    db: Arc<OpenDb>,
+    write_batch: Mutex<Option<Batch>>,
+    write_settings: WriteSettings,
+}
+
+impl RocksDBWriteBatch {
+    /// Creates a started write batch on an already open database, written with
+    /// `write_settings` unless `write()` overrides them.
+    pub fn new(db: Arc<OpenDb>, write_settings: WriteSettings) -> Self {
+        let batch = db.batch();
+        RocksDBWriteBatch {
+            db,
+            write_batch: Mutex::new(Some(batch)),
+            write_settings,
+        }
+    }
+
+    fn column_family(&self, cf_name: Option<&str>) -> PhpResult<Arc<BoundColumnFamily<'_>>> {
+        self.db
+            .cf_handle(cf_name.unwrap_or(DEFAULT_COLUMN_FAMILY_NAME))
+            .ok_or_else(|| "Column family not found".into())
+    }
 }
 
 #[php_impl]
@@ -29,8 +53,9 @@ impl RocksDBWriteBatch {
 
         match db {
             Ok(db) => Ok(RocksDBWriteBatch {
-                db: Arc::new(db),
+                db: Arc::new(OpenDb::new(DbHandle::Plain(db), BTreeMap::new(), None)),
                 write_batch: Mutex::new(None),
+                write_settings: WriteSettings::default(),
             }),
             Err(e) => Err(e.to_string().into()),
         }
@@ -38,79 +63,74 @@ impl RocksDBWriteBatch {
 
     pub fn start(&self) -> PhpResult<()> {
         let mut batch = self.write_batch.lock().unwrap();
-        *batch = Some(WriteBatchWithTransaction::<false>::default());
+        *batch = Some(self.db.batch());
         Ok(())
     }
 
-    pub fn put(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
+    pub fn put(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+    ) -> PhpResult<()> {
         let mut batch = self.write_batch.lock().unwrap();
         if let Some(ref mut wb) = *batch {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    wb.put_cf(&cf, key.as_bytes(), value.as_bytes());
-                }
-                None => {
-                    wb.put(key.as_bytes(), value.as_bytes());
-                }
-            }
+            let cf = self.column_family(cf_name.as_deref())?;
+            wb.put_cf(&cf, key.as_slice(), value.as_slice());
         } else {
             return Err("WriteBatch not initialized".into());
         }
         Ok(())
     }
 
-    pub fn merge(&self, key: String, value: String, cf_name: Option<String>) -> PhpResult<()> {
+    pub fn merge(
+        &self,
+        key: Binary<u8>,
+        value: Binary<u8>,
+        cf_name: Option<String>,
+    ) -> PhpResult<()> {
         let mut batch = self.write_batch.lock().unwrap();
         if let Some(ref mut wb) = *batch {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    wb.merge_cf(&cf, key.as_bytes(), value.as_bytes());
-                }
-                None => {
-                    wb.merge(key.as_bytes(), value.as_bytes());
-                }
-            }
+            let cf = self.column_family(cf_name.as_deref())?;
+            wb.merge_cf(&cf, key.as_slice(), value.as_slice());
         } else {
             return Err("WriteBatch not initialized".into());
         }
         Ok(())
     }
 
-    pub fn delete(&self, key: String, cf_name: Option<String>) -> PhpResult<()> {
+    pub fn delete(&self, key: Binary<u8>, cf_name: Option<String>) -> PhpResult<()> {
         let mut batch = self.write_batch.lock().unwrap();
         if let Some(ref mut wb) = *batch {
-            match cf_name {
-                Some(cf_name) => {
-                    let cf = self
-                        .db
-                        .cf_handle(&cf_name)
-                        .ok_or("Column family not found")?;
-                    wb.delete_cf(&cf, key.as_bytes());
-                }
-                None => {
-                    wb.delete(key.as_bytes());
-                }
-            }
+            let cf = self.column_family(cf_name.as_deref())?;
+            wb.delete_cf(&cf, key.as_slice());
+        } else {
+            return Err("WriteBatch not initialized".into());
+        }
+        Ok(())
+    }
+
+    pub fn delete_range(
+        &self,
+        from: Binary<u8>,
+        to: Binary<u8>,
+        cf_name: Option<String>,
+    ) -> PhpResult<()> {
+        let mut batch = self.write_batch.lock().unwrap();
+        if let Some(ref mut wb) = *batch {
+            let cf = self.column_family(cf_name.as_deref())?;
+            wb.delete_range_cf(&cf, from.as_slice(), to.as_slice())?;
         } else {
             return Err("WriteBatch not initialized".into());
         }
         Ok(())
     }
 
-    pub fn write(&self) -> PhpResult<()> {
+    pub fn write(&self, write_options: Option<&ZendHashTable>) -> PhpResult<()> {
+        let write_opts = self.write_settings.with(write_options)?.write_options();
         let mut batch = self.write_batch.lock().unwrap();
         if let Some(wb) = batch.take() {
-            self.db
-                .write(wb)
-                .map_err(|e| PhpException::from_class::<RocksDBException>(e.to_string()))?;
+            self.db.write_opt(wb, &write_opts)?;
         } else {
             return Err("WriteBatch not initialized".into());
         }
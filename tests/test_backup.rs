@@ -57,3 +57,207 @@ fn test_restore_backup() {
     // Проверяем, что значение "value1" было восстановлено
     assert_eq!(output.trim(), "value1");
 }
+
+#[test]
+fn test_backup_engine() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_backup_engine";
+        $backupPath = __DIR__ . "/temp/backup_engine";
+        $restorePath = __DIR__ . "/temp/restoredb_backup_engine";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $backup = $db->backupEngine($backupPath);
+        $backup->create();
+        $backup->restore(1, $restorePath);
+        $backup = null;
+        $db = null; // Free the connection
+
+        $db = new RocksDB($restorePath);
+        echo $db->get("key1");
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "value1");
+}
+
+#[test]
+fn test_backup_info_verify_delete() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_backup_info";
+        $backupPath = __DIR__ . "/temp/backup_info_" . uniqid();
+        $db = new RocksDB($dbPath);
+        $backup = $db->backupEngine($backupPath);
+        $db->put("key1", "value1");
+        $backup->create();
+        $db->put("key2", "value2");
+        $backup->create();
+
+        $info = $backup->info();
+        echo implode(",", array_column($info, "backup_id")) . "\n";
+        echo var_export($info[0]["app_metadata"], true) . "\n";
+        $backup->verify(1);
+        $backup->verify(2);
+        echo "verified\n";
+
+        $backup->deleteOldest();
+        echo implode(",", array_column($backup->info(), "backup_id")) . "\n";
+
+        try {
+            $backup->verify(1);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "missing\n";
+        }
+        $backup->deleteOldest();
+        try {
+            $backup->deleteOldest();
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $backup = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "1,2\nNULL\nverified\n2\nmissing\nNo backup to delete"
+    );
+}
+
+#[test]
+fn test_restore_latest() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_restore_latest";
+        $backupPath = __DIR__ . "/temp/backup_restore_latest_" . uniqid();
+        $restorePath = __DIR__ . "/temp/restoredb_latest_" . uniqid();
+        $db = new RocksDB($dbPath);
+        $backup = $db->backupEngine($backupPath);
+        $db->put("key1", "value1");
+        $db->flush();
+        $backup->create();
+        $db->put("key1", "value2");
+        $db->flush();
+        $backup->create();
+
+        try {
+            $backup->restoreLatest($dbPath);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "refused\n";
+        }
+        $backup->restoreLatest($restorePath, ["wal_dir" => $restorePath . "_wal", "keep_log_files" => false]);
+        $backup->restore(1, $restorePath . "_first");
+        $backup = null;
+        $db = null; // Free the connection
+
+        $db = new RocksDB($restorePath);
+        echo $db->get("key1") . "\n";
+        $db = null;
+        $db = new RocksDB($restorePath . "_first");
+        echo $db->get("key1") . "\n";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "refused\nvalue2\nvalue1");
+}
+
+#[test]
+fn test_restore_into_open_db() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_restore_into_open";
+        $backupPath = __DIR__ . "/temp/backup_restore_into_open_" . uniqid();
+        $otherPath = __DIR__ . "/temp/restoredb_other_" . uniqid();
+        $persistentPath = __DIR__ . "/temp/restoredb_persistent_" . uniqid();
+        $db = new RocksDB($dbPath);
+        $backup = $db->backupEngine($backupPath);
+        $db->put("key1", "value1");
+        $backup->create();
+
+        // Held by another instance: its lock file is taken.
+        $other = new RocksDB($otherPath);
+        try {
+            $backup->restoreLatest($otherPath);
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo "locked\n";
+        }
+        $other = null;
+
+        $persistent = new RocksDB($persistentPath, null, ["persistent" => true]);
+        $persistent->close();
+        try {
+            $backup->restoreLatest($persistentPath);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "refused\n";
+        }
+
+        $backup->restoreLatest($otherPath);
+        $backup = null;
+        $db = null; // Free the connection
+        $db = new RocksDB($otherPath);
+        echo $db->get("key1");
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "locked\nrefused\nvalue1");
+}
+
+#[test]
+fn test_backup_engine_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_backup_options";
+        $backupPath = __DIR__ . "/temp/backup_options_" . uniqid();
+        $db = new RocksDB($dbPath);
+        $backup = $db->backupEngine($backupPath, ["max_background_operations" => 2]);
+        $db->put("key1", "value1");
+        $backup->create(true, "schema-1");
+        $backup->create();
+        $backup->create(false, "schema-2");
+        foreach ($backup->info() as $info) {
+            echo $info["backup_id"] . "=" . var_export($info["app_metadata"], true) . "\n";
+        }
+        $backup->purgeOld(1);
+        echo json_encode(scandir($backupPath . "/app_metadata")) . "\n";
+
+        // Left behind by an engine that purged without cleaning up.
+        file_put_contents($backupPath . "/app_metadata/4", "stale");
+        file_put_contents($backupPath . "/app_metadata/9", "stale");
+        $backup->create();
+        echo json_encode(array_column($backup->info(), "app_metadata", "backup_id")) . "\n";
+        echo json_encode(scandir($backupPath . "/app_metadata")) . "\n";
+
+        try {
+            $backup->init($backupPath, ["share_table_files" => false]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $backup = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "1='schema-1'",
+            "2=NULL",
+            "3='schema-2'",
+            r#"[".","..","3"]"#,
+            r#"{"3":"schema-2","4":null}"#,
+            r#"[".","..","3"]"#,
+            "Unknown option 'share_table_files'",
+        ]
+        .join("\n")
+    );
+}
@@ -63,3 +63,40 @@ fn test_list_column_families() {
     assert!(output.contains("cf1"));
     assert!(output.contains("cf2"));
 }
+
+#[test]
+fn test_column_family_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_cf_options";
+        $db = new RocksDB($dbPath, null, [
+            "create_missing_column_families" => true,
+            "column_families" => [
+                "sessions" => ["compression" => "lz4", "bloom_filter_bits" => 10, "prefix_extractor" => 4],
+                "archive" => ["compression" => "zstd", "block_size" => 64 * 1024, "merge_operator" => "string_append"],
+            ],
+        ]);
+        $db->put("sess:1", "a", "sessions");
+        $db->merge("log", "one", "archive");
+        $db->merge("log", "two", "archive");
+        $db->createColumnFamily("counters", ["merge_operator" => "none", "compression" => "none"]);
+        $db->put("hits", "1", "counters");
+        echo $db->get("sess:1", "sessions") . "\n";
+        echo $db->get("log", "archive") . "\n";
+        echo $db->get("hits", "counters") . "\n";
+
+        try {
+            $db->createColumnFamily("broken", ["ttl" => 60]);
+        } catch (\RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "a\none,two\n1\nOption 'ttl' is not supported per column family, column families use the database TTL"
+    );
+}
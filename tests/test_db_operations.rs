@@ -209,3 +209,513 @@ fn test_all() {
     );
     assert_eq!(output.trim(), r#"{"key1":"value1","key2":"value2"}"#);
 }
+
+#[test]
+fn test_binary_keys_and_values() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_binary";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $key = "\x00\xff\xfekey\x00";
+        $value = "\x80\x81\x00\xc3\x28";
+        $db->put($key, $value);
+        echo bin2hex($db->get($key)) . "\n";
+
+        $all = $db->all();
+        echo bin2hex(array_key_first($all)) . "\n";
+        echo bin2hex($all[$key]);
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "808100c328\n00fffe6b657900\n808100c328");
+}
+
+#[test]
+fn test_constructor_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_options";
+        $db = new RocksDB($dbPath, null, [
+            "create_if_missing" => true,
+            "max_open_files" => 500,
+            "compression" => "lz4",
+            "write_buffer_size" => 8 * 1024 * 1024,
+            "max_background_jobs" => 2,
+            "block_cache_size" => 16 * 1024 * 1024,
+            "bloom_filter_bits" => 10,
+            "paranoid_checks" => true,
+        ]);
+        $db->put("key1", "value1");
+        echo $db->get("key1") . "\n";
+        $db = null; // Free the connection
+
+        try {
+            new RocksDB($dbPath, null, ["no_such_option" => 1]);
+        } catch (\RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        try {
+            new RocksDB($dbPath, null, ["max_open_files" => 4294967296]);
+        } catch (\RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "value1\nUnknown option 'no_such_option'\nOption 'max_open_files' is out of range"
+    );
+}
+
+#[test]
+fn test_multi_get() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_multi_get";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->createColumnFamily("other");
+        $db->put("key1", "value1");
+        $db->put("key2", "value2");
+        $db->put("key3", "value3", "other");
+
+        echo json_encode($db->multiGet(["key2", "missing", "key1"])) . "\n";
+        $values = $db->multiGetCf(["default" => ["key1"], "other" => ["key3", "key1"]]);
+        ksort($values);
+        echo json_encode($values);
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "{\"key2\":\"value2\",\"missing\":null,\"key1\":\"value1\"}\n{\"default\":{\"key1\":\"value1\"},\"other\":{\"key3\":\"value3\",\"key1\":null}}"
+    );
+}
+
+#[test]
+fn test_numeric_string_keys() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_numeric_keys";
+        $db = new RocksDB($dbPath);
+        foreach (["42", "-7", "007", "-0", "9223372036854775808"] as $key) {
+            $db->put($key, "v" . $key);
+        }
+
+        $values = $db->multiGet(["42", "-7", "007", "-0", "9223372036854775808", "43"]);
+        echo $values["42"] . "," . $values[-7] . "," . $values["007"] . "," . $values["-0"] . "\n";
+        echo var_export(array_keys($values), true) === var_export([42, -7, "007", "-0", "9223372036854775808", 43], true) ? "ok\n" : "mismatch\n";
+        echo var_export(array_key_exists(43, $values) && $values["43"] === null, true) . "\n";
+        $all = $db->all();
+        echo $all["42"] . "\n";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "v42,v-7,v007,v-0\nok\ntrue\nv42");
+}
+
+#[test]
+fn test_scan() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_scan";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("a:1", "value1");
+        $db->put("b:1", "value2");
+        $db->put("b:2", "value3");
+        $db->put("b:3", "value4");
+        $db->put("c:1", "value5");
+
+        $page = $db->scan("a:1", "c:1", 2);
+        echo json_encode($page) . "\n";
+        $page = $db->scan("a:1", "c:1", 2, false, null, $page['next']);
+        echo json_encode($page) . "\n";
+        $page = $db->scanPrefix("b:", 0, true);
+        echo json_encode(array_keys($page['entries'])) . "\n";
+        $page = $db->scanPrefix("b:", 2, true);
+        $page = $db->scanPrefix("b:", 2, true, null, $page['next']);
+        echo json_encode($page);
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            r#"{"entries":{"a:1":"value1","b:1":"value2"},"next":"b:2"}"#,
+            r#"{"entries":{"b:2":"value3","b:3":"value4"},"next":null}"#,
+            r#"["b:3","b:2","b:1"]"#,
+            r#"{"entries":{"b:1":"value2"},"next":null}"#,
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_delete_range() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_delete_range";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("a:1", "value1");
+        $db->put("b:1", "value2");
+        $db->put("b:2", "value3");
+        $db->put("c:1", "value4");
+        $db->put("c:2", "value5");
+
+        $db->deleteRange("b:1", "b:2");
+        echo json_encode($db->keys()) . "\n";
+        $db->deletePrefix("c:", null, true);
+        echo json_encode($db->keys());
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "[\"a:1\",\"b:2\",\"c:1\",\"c:2\"]\n[\"a:1\",\"b:2\"]"
+    );
+}
+
+#[test]
+fn test_write_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_write_options";
+        $db = new RocksDB($dbPath, null, ["write_options" => ["sync" => true]]);
+        $db->put("key1", "value1");
+        $db->put("key2", "value2", null, ["sync" => false, "disable_wal" => true]);
+        $db->delete("key1", null, ["low_pri" => true]);
+        echo json_encode($db->keys()) . "\n";
+        try {
+            $db->put("key3", "value3", null, ["fsync" => true]);
+        } catch (\RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "[\"key2\"]\nUnknown option 'fsync'");
+}
+
+#[test]
+fn test_read_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_read_options";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("key1", "value1");
+        $db->put("key2", "value2");
+
+        echo $db->get("key1", null, ["fill_cache" => false, "verify_checksums" => true]) . "\n";
+        echo json_encode($db->multiGet(["key1", "key2"], null, ["fill_cache" => false])) . "\n";
+        $page = $db->scan(null, null, 0, false, null, null, ["readahead_size" => 2 * 1024 * 1024]);
+        echo json_encode($page['entries']) . "\n";
+        foreach ($db->iterator(null, ["total_order_seek" => true, "fill_cache" => false]) as $key => $value) {
+            echo "$key=$value ";
+        }
+        echo "\n";
+        echo $db->get("key2", null, ["deadline" => 1000, "io_timeout" => 500]) . "\n";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "value1",
+            r#"{"key1":"value1","key2":"value2"}"#,
+            r#"{"key1":"value1","key2":"value2"}"#,
+            "key1=value1 key2=value2 ",
+            "value2",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_lock_timeout() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_lock_timeout";
+        $db = new RocksDB($dbPath);
+        try {
+            new RocksDB($dbPath, null, ["lock_timeout_ms" => 0]);
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo get_class($e) . "\n";
+            echo ($e instanceof RocksDB\Exception\RocksDBException ? "yes" : "no") . "\n";
+            echo (strpos($e->getMessage(), "held by process " . getmypid()) !== false ? "pid" : "no pid");
+        }
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "RocksDB\\Exception\\LockTimeoutException\nyes\npid"
+    );
+}
+
+#[test]
+fn test_close() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_close";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        echo var_export($db->isOpen(), true) . "\n";
+        $db->close();
+        $db->close();
+        echo var_export($db->isOpen(), true) . "\n";
+        try {
+            $db->get("key1");
+        } catch (RocksDB\Exception\ClosedException $e) {
+            echo $e->getMessage() . "\n";
+        }
+
+        // The lock is released, so the path can be opened again right away.
+        $other = new RocksDB($dbPath, null, ["lock_timeout_ms" => 0]);
+        echo $other->get("key1") . "\n";
+        $iterator = $other->iterator();
+        try {
+            $other->close();
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "in use\n";
+        }
+        $iterator = null;
+        $other->close();
+        echo var_export($other->isOpen(), true);
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "true\nfalse\nThe database is closed\nvalue1\nin use\nfalse"
+    );
+}
+
+#[test]
+fn test_lock_held_until_last_user() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_lock_held_until_last_user";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $iterator = $db->iterator();
+        $db = null;
+
+        // The iterator still keeps the database open, and with it the lock.
+        try {
+            new RocksDB($dbPath, null, ["lock_timeout_ms" => 0]);
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo "locked\n";
+        }
+
+        $iterator = null;
+        $db = new RocksDB($dbPath, null, ["lock_timeout_ms" => 0]);
+        echo $db->get("key1");
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "locked\nvalue1");
+}
+
+#[test]
+fn test_persistent() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_persistent";
+        $db = new RocksDB($dbPath, null, ["persistent" => true]);
+        $db->put("key1", "value1");
+        $db->close();
+
+        // Reuses the open handle instead of waiting for the lock file.
+        $db = new RocksDB($dbPath, null, ["persistent" => true]);
+        $other = new RocksDB($dbPath, null, ["persistent" => true]);
+        echo $other->get("key1") . "\n";
+        $db = null;
+        echo var_export($other->isOpen(), true) . "\n";
+
+        // Spelled differently, the path still resolves to the open handle.
+        $same = new RocksDB(dirname($dbPath) . "/../temp/" . basename($dbPath), null, ["persistent" => true, "lock_timeout_ms" => 0]);
+        echo $same->get("key1") . "\n";
+
+        // Rather than waiting for our own lock file, other ways to open the path throw.
+        try {
+            new RocksDB($dbPath, 3600, ["persistent" => true]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "different ttl\n";
+        }
+        try {
+            new RocksDB($dbPath, null, ["persistent" => true, "max_open_files" => 100]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "different options\n";
+        }
+        try {
+            new RocksDB($dbPath);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "locked";
+        }
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "value1",
+            "true",
+            "value1",
+            "different ttl",
+            "different options",
+            "locked",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_persistent_options_order() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_persistent_options_order";
+        $db = new RocksDB($dbPath, null, [
+            "persistent" => true,
+            "create_if_missing" => true,
+            "max_open_files" => 10,
+            "column_families" => ["a" => ["block_size" => 4096, "compression" => "lz4"]],
+            "create_missing_column_families" => true,
+        ]);
+        $db->put("key1", "value1", "a");
+
+        // The same options written in another order reuse the open handle.
+        $other = new RocksDB($dbPath, null, [
+            "create_missing_column_families" => true,
+            "column_families" => ["a" => ["compression" => "lz4", "block_size" => 4096]],
+            "max_open_files" => 10,
+            "create_if_missing" => true,
+            "persistent" => true,
+        ]);
+        echo $other->get("key1", "a");
+    "#,
+    );
+    assert_eq!(output.trim(), "value1");
+}
+
+#[test]
+fn test_open_read_only() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_open_read_only";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $db->flush();
+
+        // No lock file is taken, so this works while the writer is open.
+        $reader = RocksDB::openReadOnly($dbPath);
+        echo $reader->get("key1") . "\n";
+        try {
+            $reader->put("key2", "value2");
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $reader = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "value1\nput() is not supported in read-only mode"
+    );
+}
+
+#[test]
+fn test_open_as_secondary() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_open_as_secondary";
+        $secondaryPath = __DIR__ . "/temp/testdb_open_as_secondary_secondary";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+
+        $secondary = RocksDB::openAsSecondary($dbPath, $secondaryPath);
+        echo $secondary->get("key1") . "\n";
+        $db->put("key2", "value2");
+        echo var_export($secondary->get("key2"), true) . "\n";
+        $secondary->tryCatchUpWithPrimary();
+        echo $secondary->get("key2") . "\n";
+
+        try {
+            $db->tryCatchUpWithPrimary();
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+        $secondary = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "value1\nNULL\nvalue2\ntryCatchUpWithPrimary() is not supported in read-write mode"
+    );
+}
+
+#[test]
+fn test_checkpoint() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_checkpoint";
+        $checkpointPath = __DIR__ . "/temp/testdb_checkpoint_copy_" . uniqid();
+        $exportPath = __DIR__ . "/temp/testdb_checkpoint_export_" . uniqid();
+        $db = new RocksDB($dbPath, null, ["create_missing_column_families" => true, "column_families" => ["cf1" => ["compression" => "none", "block_size" => 8 * 1024]]]);
+        $db->put("key1", "value1");
+        $db->put("key2", "value2", "cf1");
+        $db->createCheckpoint($checkpointPath);
+
+        $copy = new RocksDB($checkpointPath);
+        echo $copy->get("key1") . "\n";
+        echo $copy->get("key2", "cf1") . "\n";
+        $copy = null;
+
+        echo basename($db->exportColumnFamily("cf1", $exportPath)) . "\n";
+        $db->createColumnFamily("empty");
+        echo var_export($db->exportColumnFamily("empty", $exportPath . "_empty"), true) . "\n";
+        echo var_export(file_exists($exportPath . "_empty"), true) . "\n";
+
+        try {
+            $db->createCheckpoint($checkpointPath);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo "exists";
+        }
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "value1\nvalue2\nexport.sst\nNULL\nfalse\nexists"
+    );
+}
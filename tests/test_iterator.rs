@@ -104,3 +104,120 @@ fn test_valid() {
     );
     assert_eq!(output.trim(), "true");
 }
+
+#[test]
+fn test_cursor_after_close() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_cursor_after_close";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $db->seekToFirst();
+        $db->close();
+
+        foreach (["valid", "next", "prev"] as $method) {
+            try {
+                $db->$method();
+            } catch (RocksDB\Exception\ClosedException $e) {
+                echo "$method: " . $e->getMessage() . "\n";
+            }
+        }
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "valid: The database is closed\nnext: The database is closed\nprev: The database is closed"
+    );
+}
+
+#[test]
+fn test_iterator_object() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_iterator_object";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("a:1", "value1");
+        $db->put("b:1", "value2");
+        $db->put("b:2", "value3");
+        $db->put("c:1", "value4");
+
+        $result = [];
+        foreach ($db->iterator(null, ["prefix" => "b:"]) as $key => $value) {
+            $result[$key] = $value;
+        }
+        echo json_encode($result) . "\n";
+
+        $iterator = $db->iterator(null, ["lower_bound" => "a:1", "upper_bound" => "c:1"]);
+        $iterator->seekForPrev("b:5");
+        echo $iterator->key() . "\n";
+        $iterator->next();
+        echo $iterator->valid() ? 'true' : 'false';
+        echo "\n";
+        $iterator->seek("b:1");
+        $iterator->next();
+        $iterator->prev();
+        echo $iterator->key() . "=" . $iterator->value() . "\n";
+        $iterator->status();
+        echo "ok";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "{\"b:1\":\"value2\",\"b:2\":\"value3\"}\nb:2\nfalse\nb:1=value2\nok"
+    );
+}
+
+#[test]
+fn test_snapshot_iterator() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_snapshot_iterator";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("key1", "value1");
+
+        $snapshot = $db->snapshot();
+        $iterator = $snapshot->iterator();
+        $snapshot->release();
+        $db->put("key2", "value2");
+
+        $result = [];
+        foreach ($iterator as $key => $value) {
+            $result[$key] = $value;
+        }
+        echo json_encode($result);
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), r#"{"key1":"value1"}"#);
+}
+
+#[test]
+fn test_iterator_outlives_db() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_iterator_outlives_db";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $db->put("key2", "value2");
+        $iterator = $db->iterator();
+        $db = null;
+
+        $result = [];
+        foreach ($iterator as $key => $value) {
+            $result[$key] = $value;
+        }
+        echo json_encode($result);
+        $iterator = null;
+    "#,
+    );
+    assert_eq!(output.trim(), r#"{"key1":"value1","key2":"value2"}"#);
+}
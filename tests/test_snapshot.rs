@@ -0,0 +1,90 @@
+use std::thread::sleep;
+use std::time;
+
+mod common;
+use common::php_request;
+
+fn setup() {
+    common::setup();
+    sleep(time::Duration::from_secs(1));
+}
+
+#[test]
+fn test_snapshot_get() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_snapshot";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("key1", "value1");
+        $db->put("key2", "value2");
+
+        $snapshot = $db->snapshot();
+        $db->put("key1", "new_value1");
+        $db->delete("key2");
+
+        echo $snapshot->get("key1") . "\n";
+        echo $snapshot->get("key2") . "\n";
+        echo $db->get("key1") . "\n";
+        echo $db->get("key2") ? $db->get("key2") : 'NULL';
+        $snapshot = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "value1\nvalue2\nnew_value1\nNULL");
+}
+
+#[test]
+fn test_snapshot_multi_get_and_all() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_snapshot_multi";
+        $db = new RocksDB($dbPath, 3600); // 3600 seconds TTL
+        $db->put("key1", "value1");
+        $db->put("key2", "value2");
+
+        $snapshot = $db->snapshot();
+        $db->put("key3", "value3");
+
+        $values = $snapshot->multiGet(["key1", "key3"]);
+        ksort($values);
+        echo json_encode($values) . "\n";
+        $all = $snapshot->all();
+        ksort($all);
+        echo json_encode($all);
+        $snapshot->release();
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "{\"key1\":\"value1\",\"key3\":null}\n{\"key1\":\"value1\",\"key2\":\"value2\"}"
+    );
+}
+
+#[test]
+fn test_snapshot_outlives_db() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_snapshot_outlives_db";
+        $db = new RocksDB($dbPath);
+        $db->put("key1", "value1");
+        $snapshot = $db->snapshot();
+        $iterator = $snapshot->iterator();
+        $db->put("key1", "value2");
+        $db = null;
+
+        echo $snapshot->get("key1") . "\n";
+        $snapshot = null;
+        $iterator->rewind();
+        echo $iterator->current() . "\n";
+        $iterator = null;
+    "#,
+    );
+    assert_eq!(output.trim(), "value1\nvalue1");
+}
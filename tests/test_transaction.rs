@@ -85,3 +85,331 @@ fn test_savepoint_transaction() {
     let expected_output = "value3\nNULL";
     assert_eq!(output.trim(), expected_output);
 }
+
+#[test]
+fn test_binary_transaction() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_binary";
+
+        $transaction = new RocksDBTransaction($dbPath);
+        $transaction->put("\x00\xffkey", "\xc3\x28\x00value");
+        $transaction->commit();
+        $transaction->begin();
+        echo bin2hex($transaction->get("\x00\xffkey"));
+        $transaction = null; // Free the connection
+    "#,
+    );
+
+    assert_eq!(output.trim(), "c3280076616c7565");
+}
+
+#[test]
+fn test_transaction_write_options() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_write_options";
+        $transaction = new RocksDBTransaction($dbPath, ["sync" => true]);
+        $transaction->put("key1", "value1");
+        $transaction->commit();
+        $transaction->begin();
+        echo $transaction->get("key1");
+        $transaction = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "value1");
+}
+
+#[test]
+fn test_begin_transaction() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_begin_transaction";
+        $db = new RocksDB($dbPath, null, ["transactional" => true]);
+        $transaction = $db->beginTransaction();
+        $transaction->put("key1", "value1");
+        echo var_export($db->get("key1"), true) . "\n";
+        $transaction->commit();
+        echo $db->get("key1") . "\n";
+        $transaction = null;
+
+        try {
+            $db->flush();
+        } catch (Exception $e) {
+            echo $e->getMessage();
+        }
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        "NULL\nvalue1\nflush() is not supported in transactional mode"
+    );
+}
+
+#[test]
+fn test_transaction_lock_timeout() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_lock_timeout";
+        $db = new RocksDB($dbPath, null, ["transactional" => true, "default_lock_timeout" => 10, "max_num_locks" => 100]);
+        $first = $db->beginTransaction();
+        $first->put("key1", "first");
+
+        $second = $db->beginTransaction(["lock_timeout" => 10, "deadlock_detect" => true, "set_snapshot" => true]);
+        try {
+            $second->put("key1", "second");
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo get_class($e) . "\n";
+        }
+        try {
+            $db->put("key1", "direct");
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo get_class($e) . "\n";
+        }
+        $first->commit();
+        $second->rollback();
+        echo $db->get("key1") . "\n";
+        $first = null;
+        $second = null;
+        $db = null; // Free the connection
+
+        try {
+            new RocksDB($dbPath . "_plain", null, ["max_num_locks" => 100]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "RocksDB\\Exception\\LockTimeoutException",
+            "RocksDB\\Exception\\LockTimeoutException",
+            "first",
+            "Option 'max_num_locks' requires 'transactional' => true",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_get_for_update() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_get_for_update";
+        $db = new RocksDB($dbPath, null, ["transactional" => true, "create_missing_column_families" => true, "column_families" => ["counters" => []]]);
+        $db->put("hits", "1", "counters");
+        $db->put("a", "1");
+
+        $first = $db->beginTransaction();
+        $hits = (int) $first->getForUpdate("hits", true, "counters");
+        echo json_encode($first->multiGetForUpdate(["a", "b"])) . "\n";
+
+        $second = $db->beginTransaction(["lock_timeout" => 10]);
+        try {
+            $second->getForUpdate("hits", true, "counters");
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo "locked\n";
+        }
+        try {
+            $second->put("a", "2");
+        } catch (RocksDB\Exception\LockTimeoutException $e) {
+            echo "locked\n";
+        }
+        $first->put("hits", (string) ($hits + 1), "counters");
+        $first->commit();
+        echo $second->getForUpdate("hits", false, "counters") . "\n";
+        $second->rollback();
+        $first = null;
+        $second = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "{\"a\":\"1\",\"b\":null}\nlocked\nlocked\n2");
+}
+
+#[test]
+fn test_optimistic_transaction_conflict() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_optimistic";
+        $db = new RocksDB($dbPath, null, ["transactional" => "optimistic"]);
+        $db->put("key1", "initial");
+
+        $first = $db->beginTransaction(["set_snapshot" => true]);
+        $second = $db->beginTransaction(["set_snapshot" => true]);
+        $first->put("key1", "first");
+        $second->put("key1", "second");
+        $first->commit();
+        try {
+            $second->commit();
+        } catch (RocksDB\Exception\ConflictException $e) {
+            echo get_class($e) . "\n";
+        }
+        echo $db->get("key1") . "\n";
+
+        try {
+            $db->beginTransaction(["lock_timeout" => 10]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        $db->flush();
+        $first = null;
+        $second = null;
+        $db = null; // Free the connection
+
+        try {
+            new RocksDB($dbPath, null, ["transactional" => "optimistic", "max_num_locks" => 100]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        try {
+            new RocksDB($dbPath, null, ["transactional" => "eventual"]);
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage();
+        }
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "RocksDB\\Exception\\ConflictException",
+            "first",
+            "Option 'lock_timeout' is not supported in optimistic mode",
+            "Option 'max_num_locks' is not supported in optimistic mode",
+            "Unknown transaction mode 'eventual', expected 'pessimistic' or 'optimistic'",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_transaction_lifecycle() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_lifecycle";
+        $db = new RocksDB($dbPath, null, ["transactional" => true]);
+
+        $transaction = $db->beginTransaction();
+        $transaction->put("key1", "value1");
+        $transaction->commit();
+        echo var_export($transaction->isActive(), true) . "\n";
+        try {
+            $transaction->put("key2", "value2");
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        try {
+            $transaction->commit();
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        $transaction->rollback();
+
+        $transaction->begin();
+        echo var_export($transaction->isActive(), true) . "\n";
+        try {
+            $transaction->begin();
+        } catch (RocksDB\Exception\RocksDBException $e) {
+            echo $e->getMessage() . "\n";
+        }
+        $transaction->put("key2", "value2");
+        $transaction = null; // Rolled back
+
+        $transaction = $db->beginTransaction(["commit_on_destruct" => true]);
+        $transaction->put("key3", "value3");
+        $transaction = null; // Committed
+
+        echo $db->get("key1") . "\n";
+        echo var_export($db->get("key2"), true) . "\n";
+        echo $db->get("key3") . "\n";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(
+        output.trim(),
+        [
+            "false",
+            "No active transaction",
+            "No active transaction",
+            "true",
+            "Transaction is already active",
+            "value1",
+            "NULL",
+            "value3",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn test_transaction_drop_order() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_transaction_drop_order";
+
+        // The database goes first; the transaction keeps its handle alive.
+        $db = new RocksDB($dbPath, null, ["transactional" => true]);
+        $transaction = $db->beginTransaction();
+        $db = null;
+        $transaction->put("key1", "value1");
+        $transaction->commit();
+        $transaction = null;
+
+        // The transaction goes first, still active.
+        $db = new RocksDB($dbPath, null, ["transactional" => true]);
+        $transaction = $db->beginTransaction();
+        $transaction->put("key2", "value2");
+        $transaction = null;
+        echo var_export($db->get("key2"), true) . "\n";
+        $db = null;
+
+        // Both are only reachable from a cycle, so the garbage collector
+        // decides the order.
+        foreach ([false, true] as $reversed) {
+            $db = new RocksDB($dbPath, null, ["transactional" => true]);
+            $transaction = $db->beginTransaction(["commit_on_destruct" => true]);
+            $transaction->put("key3", $reversed ? "reversed" : "forward");
+            $cycle = $reversed ? [$transaction, $db] : [$db, $transaction];
+            $cycle[] = &$cycle;
+            $db = null;
+            $transaction = null;
+            unset($cycle);
+            gc_collect_cycles();
+        }
+
+        // The standalone constructor owns the only handle.
+        $transaction = new RocksDBTransaction($dbPath);
+        $transaction->put("key4", "value4");
+        $cycle = [$transaction];
+        $cycle[] = &$cycle;
+        $transaction = null;
+        unset($cycle);
+        gc_collect_cycles();
+
+        $db = new RocksDB($dbPath, null, ["transactional" => true]);
+        echo $db->get("key1") . "\n";
+        echo $db->get("key3") . "\n";
+        echo var_export($db->get("key4"), true) . "\n";
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "NULL\nvalue1\nreversed\nNULL");
+}
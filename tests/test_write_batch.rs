@@ -33,3 +33,75 @@ fn test_write_batch() {
     let expected_output = "value1\nvalue2";
     assert_eq!(output.trim(), expected_output);
 }
+
+#[test]
+fn test_write_batch_delete_range() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_write_batch_delete_range";
+        $write_batch = new RocksDBWriteBatch($dbPath, 3600); // 3600 seconds TTL
+        $write_batch->start();
+        $write_batch->put("key1", "value1");
+        $write_batch->put("key2", "value2");
+        $write_batch->put("key3", "value3");
+        $write_batch->deleteRange("key1", "key3");
+        $write_batch->write();
+        $write_batch = null; // Free the connection
+
+        $db = new RocksDB($dbPath, 3600);
+        echo json_encode($db->keys());
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), r#"["key3"]"#);
+}
+
+#[test]
+fn test_create_write_batch() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_create_write_batch";
+        $db = new RocksDB($dbPath);
+        $write_batch = $db->createWriteBatch();
+        $write_batch->put("key1", "value1");
+        $write_batch->put("key2", "value2");
+        echo var_export($db->get("key1"), true) . "\n";
+        $write_batch->write();
+        echo $db->get("key1") . "\n" . $db->get("key2");
+        $write_batch = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "NULL\nvalue1\nvalue2");
+}
+
+#[test]
+fn test_create_write_batch_write_defaults() {
+    setup();
+    let output = php_request(
+        r#"
+        <?php
+        $dbPath = __DIR__ . "/temp/testdb_create_write_batch_write_defaults";
+        $db = new RocksDB($dbPath, null, ["write_options" => ["disable_wal" => true]]);
+        $write_batch = $db->createWriteBatch();
+        $write_batch->put("key1", "value1");
+        // RocksDB refuses synced writes without the WAL, so this only fails if the default applies.
+        try {
+            $write_batch->write(["sync" => true]);
+        } catch (Exception $e) {
+            echo "rejected\n";
+        }
+        $write_batch->start();
+        $write_batch->put("key1", "value1");
+        $write_batch->write();
+        echo $db->get("key1");
+        $write_batch = null;
+        $db = null; // Free the connection
+    "#,
+    );
+    assert_eq!(output.trim(), "rejected\nvalue1");
+}